@@ -1,5 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use solver::Game;
+use solver::{Game, MoveOrdering, SearchMode};
 
 pub fn bench_3_3_3(c: &mut Criterion) {
     let mut game = Game::new_xo(black_box(3), black_box(3), black_box(3));
@@ -58,5 +58,48 @@ pub fn bench_7_6_4_c4(c: &mut Criterion) {
     }));
 }
 
-criterion_group!(benches, bench_3_3_3, bench_3_3_3_c4, bench_4_4_3, bench_4_4_4, bench_4_4_4_c4, bench_5_5_3_o, bench_7_6_4_c4);
+pub fn bench_move_ordering(c: &mut Criterion) {
+    let all = MoveOrdering::default();
+    let orderings = [
+        ("default", all),
+        ("none", MoveOrdering::none()),
+        ("no centre first", MoveOrdering { centre_first: false, ..all }),
+        ("no TT move", MoveOrdering { tt_move: false, ..all }),
+        ("with killers", MoveOrdering { killers: true, ..all }),
+        ("no history", MoveOrdering { history: false, ..all }),
+    ];
+
+    let mut group = c.benchmark_group("5 4 4 Connect Four move ordering");
+    for (name, ordering) in orderings {
+        let mut game = Game::new_connect_four(black_box(5), black_box(4), black_box(4));
+        game.solver.set_move_ordering(ordering);
+        group.bench_function(name, |b| b.iter(|| {
+            game.solver.clear_transpositions();
+            game.best_move()
+        }));
+    }
+    group.finish();
+}
+
+pub fn bench_search_modes(c: &mut Criterion) {
+    // The empty 7 6 board takes far too long, so start from a midgame position
+    let opening = [3, 3, 3, 3, 3, 2, 2, 2, 4, 4, 4, 1, 1, 5, 5, 0];
+
+    let mut group = c.benchmark_group("7 6 4 Connect Four search modes");
+    group.sample_size(10);
+    for mode in [SearchMode::AlphaBeta, SearchMode::Pvs, SearchMode::Mtdf] {
+        let mut game = Game::new_connect_four(7, 6, 4);
+        game.solver.set_search_mode(mode);
+        for mov in opening {
+            game.placebit(black_box(mov));
+        }
+        group.bench_function(format!("{:?}", mode), |b| b.iter(|| {
+            game.solver.clear_transpositions();
+            game.best_move()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_3_3_3, bench_3_3_3_c4, bench_4_4_3, bench_4_4_4, bench_4_4_4_c4, bench_5_5_3_o, bench_7_6_4_c4, bench_move_ordering, bench_search_modes);
 criterion_main!(benches);
@@ -0,0 +1,43 @@
+use solver::{Game, GameConfig, OpeningBook};
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::exit;
+use std::time::Instant;
+
+// Generates an opening book, e.g. `book c4 7 6 4 8 c4-7x6.book`
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 6 {
+        eprintln!("Usage: book <xo|c4> <width> <height> <row> <plies> <output file>");
+        exit(1);
+    }
+
+    let numbers: Vec<u32> = args[1..5].iter()
+        .map(|a| a.parse::<u32>().expect("Width, height, row and plies need to be numbers"))
+        .collect();
+    let config = match args[0].as_str() {
+        "xo" => GameConfig::xo(),
+        "c4" => GameConfig::connect_four(),
+        _ => {
+            eprintln!("Unknown game {}, expected xo or c4", args[0]);
+            exit(1);
+        },
+    };
+    let mut game: Game = match config.width(numbers[0]).height(numbers[1]).row(numbers[2]).build() {
+        Ok(game) => game,
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(1);
+        },
+    };
+
+    let now = Instant::now();
+    let book = OpeningBook::generate(&mut game, numbers[3], |ply, positions| {
+        eprintln!("Solving {} positions at ply {} ({}s so far)", positions, ply, now.elapsed().as_secs());
+    });
+
+    let file = File::create(&args[5]).expect("Couldn't create the book file");
+    book.write_to(BufWriter::new(file)).expect("Couldn't write the book");
+    println!("Wrote {} positions in {}s", book.len(), now.elapsed().as_secs());
+}
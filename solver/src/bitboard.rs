@@ -0,0 +1,229 @@
+use std::{fmt, hash::Hash, ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr}};
+
+// A set of cells, one bit each. u64 is quickest and fits boards up to 8x7 (each column
+// needs a spare bit), u128 goes up to 10x10 or so, and WideBitboard is for anything
+// bigger.
+pub trait Bitboard:
+    Copy + Default + Eq + Hash + fmt::Debug + fmt::Binary + Send + Sync + 'static
+    + BitAnd<Output = Self> + BitOr<Output = Self> + BitXor<Output = Self> + Not<Output = Self>
+    + Shl<u32, Output = Self> + Shr<u32, Output = Self>
+    + BitAndAssign + BitOrAssign + BitXorAssign
+{
+    const BITS: u32;
+    const ZERO: Self;
+
+    // Just the one bit set
+    fn bit(index: u32) -> Self;
+
+    // The lowest `count` bits set
+    fn low_bits(count: u32) -> Self;
+
+    fn count_ones(self) -> u32;
+
+    fn trailing_zeros(self) -> u32;
+
+    fn leading_zeros(self) -> u32;
+
+    fn wrapping_add(self, other: Self) -> Self;
+
+    // None when shifting by the whole width or more
+    fn checked_shl(self, shift: u32) -> Option<Self>;
+
+    fn checked_shr(self, shift: u32) -> Option<Self>;
+
+    fn is_empty(self) -> bool {
+        self == Self::ZERO
+    }
+}
+
+macro_rules! primitive_bitboard {
+    ($($t:ty),*) => {$(
+        impl Bitboard for $t {
+            const BITS: u32 = <$t>::BITS;
+            const ZERO: Self = 0;
+
+            fn bit(index: u32) -> Self {
+                1 << index
+            }
+
+            fn low_bits(count: u32) -> Self {
+                match count >= Self::BITS {
+                    true => !0,
+                    false => (1 << count) - 1,
+                }
+            }
+
+            fn count_ones(self) -> u32 {
+                <$t>::count_ones(self)
+            }
+
+            fn trailing_zeros(self) -> u32 {
+                <$t>::trailing_zeros(self)
+            }
+
+            fn leading_zeros(self) -> u32 {
+                <$t>::leading_zeros(self)
+            }
+
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+
+            fn checked_shl(self, shift: u32) -> Option<Self> {
+                <$t>::checked_shl(self, shift)
+            }
+
+            fn checked_shr(self, shift: u32) -> Option<Self> {
+                <$t>::checked_shr(self, shift)
+            }
+        }
+    )*};
+}
+
+primitive_bitboard!(u64, u128);
+
+// WORDS 64 bit words, lowest first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WideBitboard<const WORDS: usize>([u64; WORDS]);
+
+// Enough for 15x15 boards
+pub type Bitboard256 = WideBitboard<4>;
+
+impl<const WORDS: usize> Default for WideBitboard<WORDS> {
+    fn default() -> Self {
+        WideBitboard([0; WORDS])
+    }
+}
+
+impl<const WORDS: usize> Bitboard for WideBitboard<WORDS> {
+    const BITS: u32 = 64 * WORDS as u32;
+    const ZERO: Self = WideBitboard([0; WORDS]);
+
+    fn bit(index: u32) -> Self {
+        let mut words = [0; WORDS];
+        words[index as usize / 64] = 1 << (index % 64);
+        WideBitboard(words)
+    }
+
+    fn low_bits(count: u32) -> Self {
+        let mut words = [0; WORDS];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = u64::low_bits(count.saturating_sub(64 * i as u32));
+        }
+        WideBitboard(words)
+    }
+
+    fn count_ones(self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        match self.0.iter().position(|word| *word != 0) {
+            Some(i) => 64 * i as u32 + self.0[i].trailing_zeros(),
+            None => Self::BITS,
+        }
+    }
+
+    fn leading_zeros(self) -> u32 {
+        match self.0.iter().rposition(|word| *word != 0) {
+            Some(i) => 64 * (WORDS - 1 - i) as u32 + self.0[i].leading_zeros(),
+            None => Self::BITS,
+        }
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        let mut words = [0; WORDS];
+        let mut carry = false;
+        for (i, word) in words.iter_mut().enumerate() {
+            let (sum, overflowed) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, carried) = sum.overflowing_add(carry as u64);
+            *word = sum;
+            carry = overflowed || carried;
+        }
+        WideBitboard(words)
+    }
+
+    fn checked_shl(self, shift: u32) -> Option<Self> {
+        (shift < Self::BITS).then(|| self << shift)
+    }
+
+    fn checked_shr(self, shift: u32) -> Option<Self> {
+        (shift < Self::BITS).then(|| self >> shift)
+    }
+}
+
+macro_rules! wide_bitwise {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt) => {
+        impl<const WORDS: usize> $trait for WideBitboard<WORDS> {
+            type Output = Self;
+
+            fn $method(self, other: Self) -> Self {
+                WideBitboard(std::array::from_fn(|i| self.0[i] $op other.0[i]))
+            }
+        }
+
+        impl<const WORDS: usize> $assign_trait for WideBitboard<WORDS> {
+            fn $assign_method(&mut self, other: Self) {
+                *self = *self $op other;
+            }
+        }
+    };
+}
+
+wide_bitwise!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+wide_bitwise!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+wide_bitwise!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl<const WORDS: usize> Not for WideBitboard<WORDS> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        WideBitboard(self.0.map(|word| !word))
+    }
+}
+
+impl<const WORDS: usize> Shl<u32> for WideBitboard<WORDS> {
+    type Output = Self;
+
+    fn shl(self, shift: u32) -> Self {
+        let (words, bits) = (shift as usize / 64, shift % 64);
+        WideBitboard(std::array::from_fn(|i| {
+            let Some(from) = i.checked_sub(words) else { return 0 };
+            // The bits that move across from the word below
+            let carried = match (bits, from.checked_sub(1)) {
+                (1.., Some(below)) => self.0[below] >> (64 - bits),
+                _ => 0,
+            };
+            self.0[from] << bits | carried
+        }))
+    }
+}
+
+impl<const WORDS: usize> Shr<u32> for WideBitboard<WORDS> {
+    type Output = Self;
+
+    fn shr(self, shift: u32) -> Self {
+        let (words, bits) = (shift as usize / 64, shift % 64);
+        WideBitboard(std::array::from_fn(|i| {
+            let from = i + words;
+            if from >= WORDS { return 0; }
+            let carried = match bits {
+                1.. if from + 1 < WORDS => self.0[from + 1] << (64 - bits),
+                _ => 0,
+            };
+            self.0[from] >> bits | carried
+        }))
+    }
+}
+
+impl<const WORDS: usize> fmt::Binary for WideBitboard<WORDS> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.iter().rposition(|word| *word != 0) {
+            None => write!(f, "0"),
+            Some(top) => {
+                write!(f, "{:b}", self.0[top])?;
+                self.0[..top].iter().rev().try_for_each(|word| write!(f, "{:064b}", word))
+            },
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashSet, io::{self, Read, Write}};
+use rayon::prelude::*;
+use crate::{invalid_data, Bitboard, Board, Game, GameRules, WIN_SCORE};
+
+const MAGIC: &[u8; 4] = b"XOBK";
+const VERSION: u8 = 1;
+
+// Solved scores for every position up to some number of plies into the game, so the
+// solver doesn't have to work out the opening again every game. Positions are keyed
+// by their canonical hash, so only one of each set of symmetrical positions is stored.
+//
+// The file is a header (magic, version, rules (see Board::rules), plies and the
+// number of entries) followed by the entries sorted by key, each a little endian u64
+// key and one byte of score (see encode).
+pub struct OpeningBook {
+    plies: u32,
+    table: ScoreTable,
+}
+
+// Encoded scores sorted by canonical key, for the game the rules header (see
+// Board::rules_header) is for. What the opening book and tablebase look scores up in.
+pub(crate) struct ScoreTable {
+    rules: [u8; 4],
+    entries: Vec<(u64, i8)>,
+}
+
+impl ScoreTable {
+    pub(crate) fn new(rules: [u8; 4], mut entries: Vec<(u64, i8)>) -> ScoreTable {
+        entries.par_sort_unstable_by_key(|(key, _)| *key);
+        ScoreTable { rules, entries }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Whether the table was made for the same game as the board
+    pub(crate) fn matches<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> bool {
+        self.rules == board.rules_header()
+    }
+
+    // The score for the player to move, if the position is in the table
+    pub(crate) fn get<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Option<i32> {
+        let key = board.canonical().0;
+        self.entries.binary_search_by_key(&key, |(key, _)| *key).ok()
+            .map(|index| decode(self.entries[index].1))
+    }
+}
+
+// Solved scores only depend on how many pieces are on the board when the game ends,
+// which always fits in a byte: positive if the player to move wins, negative if they
+// lose and 0 for a draw
+pub(crate) fn encode(score: i32) -> i8 {
+    match score {
+        0 => 0,
+        x if x > 0 => (WIN_SCORE - x) as i8,
+        x => -((WIN_SCORE + x) as i8),
+    }
+}
+
+pub(crate) fn decode(value: i8) -> i32 {
+    match value {
+        0 => 0,
+        x if x > 0 => WIN_SCORE - x as i32,
+        x => -(WIN_SCORE + x as i32),
+    }
+}
+
+impl OpeningBook {
+    // Solves every position reachable in this many plies or fewer from the game's
+    // position. Can take a very long time on big boards, so it reports how far it
+    // has got to the callback (plies so far, positions at that ply).
+    pub fn generate<R: GameRules, B: Bitboard>(game: &mut Game<R, B>, plies: u32, mut progress: impl FnMut(u32, usize)) -> OpeningBook {
+        let mut entries = Vec::new();
+        let mut seen = HashSet::from([game.board.canonical().0]);
+        let mut level = vec![game.board.clone()];
+        let start = game.board.moves_played();
+
+        for ply in 0..=plies {
+            progress(ply, level.len());
+            let mut next_level = Vec::new();
+            for board in &mut level {
+                entries.push((board.canonical().0, encode(game.solver.solve_score(board))));
+                if ply == plies { continue; }
+
+                for mov in board.generate_moves() {
+                    let mut next = board.clone();
+                    next.placebit(mov);
+                    if !next.over() && seen.insert(next.canonical().0) {
+                        next_level.push(next);
+                    }
+                }
+            }
+            level = next_level;
+        }
+
+        OpeningBook {
+            plies: start + plies,
+            table: ScoreTable::new(game.board.rules_header(), entries),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Whether the book was made for the same game as the board
+    pub fn matches<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> bool {
+        self.table.matches(board)
+    }
+
+    // The solved score of the position for the player to move, if it's in the book
+    pub(crate) fn get<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Option<i32> {
+        if board.moves_played() > self.plies { return None; }
+        self.table.get(board)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.table.rules)?;
+        writer.write_all(&[self.plies as u8])?;
+        writer.write_all(&(self.table.entries.len() as u32).to_le_bytes())?;
+        for (key, value) in &self.table.entries {
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_from(mut reader: impl Read) -> io::Result<OpeningBook> {
+        let mut header = [0; 14];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC { return Err(invalid_data("not an opening book")); }
+        if header[4] != VERSION { return Err(invalid_data("unsupported opening book version")); }
+
+        let rules = header[5..9].try_into().unwrap();
+        let plies = header[9] as u32;
+        let count = u32::from_le_bytes(header[10..14].try_into().unwrap()) as usize;
+
+        let mut entries = Vec::with_capacity(count.min(1 << 20)); // Don't trust the count with memory
+        let mut entry = [0; 9];
+        for _ in 0..count {
+            reader.read_exact(&mut entry)?;
+            entries.push((u64::from_le_bytes(entry[..8].try_into().unwrap()), entry[8] as i8));
+        }
+        if !entries.is_sorted_by_key(|(key, _)| *key) {
+            return Err(invalid_data("opening book entries are out of order"));
+        }
+
+        Ok(OpeningBook { plies, table: ScoreTable { rules, entries } })
+    }
+}
@@ -0,0 +1,115 @@
+use std::{error::Error, fmt};
+use crate::{Bitboard, BoardKind, Game, GameRules};
+
+// Why a game can't be set up the way it was asked for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    // No cells at all
+    EmptyBoard { width: u32, height: u32 },
+    // Nobody can ever win with a row of 0
+    ZeroRow,
+    // A row longer than the board is wide or high can't fit on it
+    RowTooLong { row: u32, width: u32, height: u32 },
+    // More columns than the rules can handle
+    TooWide { width: u32, max_width: u32 },
+    // The board doesn't fit in the bitboard, counting the spare bit at the top of each column
+    TooBig { width: u32, height: u32, bits: u64, capacity: u32 },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::EmptyBoard { width, height } => write!(f, "A {}x{} board has no cells", width, height),
+            ConfigError::ZeroRow => write!(f, "The row needed to win can't be 0"),
+            ConfigError::RowTooLong { row, width, height } =>
+                write!(f, "A row of {} doesn't fit on a {}x{} board", row, width, height),
+            ConfigError::TooWide { width, max_width } =>
+                write!(f, "The board can't be {} wide, the most is {}", width, max_width),
+            ConfigError::TooBig { width, height, bits, capacity } =>
+                write!(f, "A {}x{} board needs {} bits, but only {} are available", width, height, bits, capacity),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+// Sets up a game, checking the board makes sense and fits in the bitboard first:
+//     let game: Game = GameConfig::connect_four().width(8).build()?;
+#[derive(Clone, Debug)]
+pub struct GameConfig<R: GameRules = BoardKind> {
+    rules: R,
+    width: u32,
+    height: u32,
+    row: u32,
+}
+
+impl GameConfig {
+    // Noughts and crosses, 3x3 with 3 in a row to start with
+    pub fn xo() -> GameConfig {
+        GameConfig::new(BoardKind::XOBoard).width(3).height(3).row(3)
+    }
+
+    // Connect Four, 7x6 with 4 in a row to start with
+    pub fn connect_four() -> GameConfig {
+        GameConfig::new(BoardKind::C4Board).width(7).height(6).row(4)
+    }
+}
+
+impl<R: GameRules> GameConfig<R> {
+    // Starts out as a 3x3 board with 3 in a row
+    pub fn new(rules: R) -> GameConfig<R> {
+        GameConfig { rules, width: 3, height: 3, row: 3 }
+    }
+
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = height;
+        self
+    }
+
+    // How many in a row wins
+    pub fn row(mut self, row: u32) -> Self {
+        self.row = row;
+        self
+    }
+
+    // Bits the board needs, counting the spare bit at the top of each column
+    pub fn bits(&self) -> u64 {
+        self.width as u64 * (self.height as u64 + 1)
+    }
+
+    // Everything build checks, for a bitboard with this many bits. Boards also can't
+    // use more than 255 bits, so every cell has a move index.
+    pub fn validate(&self, capacity: u32) -> Result<(), ConfigError> {
+        let (width, height, row) = (self.width, self.height, self.row);
+        if width == 0 || height == 0 {
+            return Err(ConfigError::EmptyBoard { width, height });
+        }
+        if row == 0 {
+            return Err(ConfigError::ZeroRow);
+        }
+        if row > width.max(height) {
+            return Err(ConfigError::RowTooLong { row, width, height });
+        }
+        if let Some(max_width) = self.rules.max_width() {
+            if width > max_width {
+                return Err(ConfigError::TooWide { width, max_width });
+            }
+        }
+
+        let capacity = capacity.min(u8::MAX as u32);
+        if self.bits() > capacity as u64 {
+            return Err(ConfigError::TooBig { width, height, bits: self.bits(), capacity });
+        }
+        Ok(())
+    }
+
+    pub fn build<B: Bitboard>(&self) -> Result<Game<R, B>, ConfigError> {
+        self.validate(B::BITS)?;
+        Ok(Game::unchecked(self.rules.clone(), self.width, self.height, self.row))
+    }
+}
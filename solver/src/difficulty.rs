@@ -0,0 +1,93 @@
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng};
+use crate::{Move, WIN_THRESHOLD};
+
+// How well the computer plays. The default is perfect play.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    // How many plies ahead it looks, None for all the way to the end of the game
+    pub max_depth: Option<u32>,
+    // Chance of playing any legal move at all instead of a good one
+    pub random_move_chance: f64,
+    // Chance of playing the next best move instead
+    pub suboptimal_move_chance: f64,
+    // Plays any winning move rather than the quickest win, so it's still hard to
+    // beat but takes its time about winning
+    pub relaxed: bool,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::perfect()
+    }
+}
+
+impl Difficulty {
+    pub fn easy() -> Self {
+        Difficulty {
+            max_depth: Some(2),
+            random_move_chance: 0.3,
+            suboptimal_move_chance: 0.3,
+            relaxed: true,
+        }
+    }
+
+    pub fn medium() -> Self {
+        Difficulty {
+            max_depth: Some(4),
+            random_move_chance: 0.1,
+            suboptimal_move_chance: 0.2,
+            relaxed: true,
+        }
+    }
+
+    pub fn hard() -> Self {
+        Difficulty {
+            max_depth: Some(8),
+            random_move_chance: 0.0,
+            suboptimal_move_chance: 0.1,
+            relaxed: false,
+        }
+    }
+
+    pub fn perfect() -> Self {
+        Difficulty {
+            max_depth: None,
+            random_move_chance: 0.0,
+            suboptimal_move_chance: 0.0,
+            relaxed: false,
+        }
+    }
+
+    // Whether it always plays the best move it finds
+    pub(crate) fn plays_best(&self) -> bool {
+        self.random_move_chance <= 0.0 && self.suboptimal_move_chance <= 0.0 && !self.relaxed
+    }
+
+    // Picks which of the scored moves to play
+    pub(crate) fn choose(&self, scores: &[(Move, i32)], rng: &mut SmallRng) -> Option<(Move, i32)> {
+        let best = *scores.iter().max_by_key(|(_, score)| *score)?;
+        if rng.gen_bool(self.random_move_chance.clamp(0.0, 1.0)) {
+            return scores.choose(rng).copied();
+        }
+
+        let candidates: Vec<(Move, i32)> = match self.relaxed {
+            // Any win will do, however long it takes
+            true if best.1 >= WIN_THRESHOLD => scores.iter().copied().filter(|(_, score)| *score >= WIN_THRESHOLD).collect(),
+            // Otherwise nothing worse than the best move, so a win is never thrown away for a draw
+            true => scores.iter().copied().filter(|(_, score)| *score >= best.1).collect(),
+            false => vec![best],
+        };
+
+        if rng.gen_bool(self.suboptimal_move_chance.clamp(0.0, 1.0)) {
+            // The best of the moves that are worse than all of the candidates
+            let worst = candidates.iter().map(|(_, score)| *score).min().unwrap();
+            let next_best = scores.iter().copied()
+                .filter(|(_, score)| *score < worst)
+                .max_by_key(|(_, score)| *score);
+            if next_best.is_some() {
+                return next_best;
+            }
+        }
+        candidates.choose(rng).copied()
+    }
+}
@@ -1,11 +1,13 @@
 use itertools::Itertools;
 use rustyline::Editor;
-use solver::Move;
+use solver::{Bitboard, Bitboard256, BoardKind, Difficulty, Engine, Game, GameConfig, OpeningBook, Outcome, Player};
+use std::fs::File;
 use std::process::exit;
 use std::time::Instant;
 use std::env;
+use std::io::{self, BufReader, BufWriter, Write};
 
-fn check_game_end(game: &solver::Game) {
+fn check_game_end<B: Bitboard>(game: &Game<BoardKind, B>) {
     if game.board.over() {
         game.board.print(false);
         if game.board.has_won(solver::Player::X) {
@@ -20,46 +22,101 @@ fn check_game_end(game: &solver::Game) {
     }
 }
 
-fn evaluation(eval: i32) -> String {
-    if eval == 0 { return "Draw".to_string() }
-
-    // 1 turns lol
-    match eval < 0 {
-        false => format!("X wins in {} turns", (eval as f32 / 2.0).ceil()), // Hopefully convert plies to turns
-        true => format!("O wins in {} turns", (eval as f32 / 2.0 * -1.0).ceil()),
+// Outcomes are for the player to move
+fn outcome(player: Player, outcome: Outcome) -> String {
+    let (winner, loser) = match player {
+        Player::X => ("X", "O"),
+        Player::O => ("O", "X"),
+    };
+    match outcome {
+        Outcome::Win => format!("{} wins", winner),
+        Outcome::Draw => "Draw".to_string(),
+        Outcome::Loss => format!("{} wins", loser),
+        Outcome::Unknown => "Unknown".to_string(),
     }
 }
 
-fn cpuplay(game: &mut solver::Game) {
+// Weak solving only says who wins, but is much quicker than finding out how soon
+fn cpuplay<B: Bitboard>(game: &mut Game<BoardKind, B>, weak: bool) {
     println!("Thinking time...");
     let now = Instant::now();
     let best_move = game.best_move();
-    println!("Thinking took {}ms", now.elapsed().as_millis());
+    eprint!("\r");
+    let stats = game.search_stats();
+    println!("Thinking took {}ms ({} nodes, {} nodes/s, {:.0}% table hits)",
+        now.elapsed().as_millis(), stats.nodes, stats.nodes_per_second(), stats.tt_hit_rate() * 100.0);
+    let line = game.principal_variation();
+    println!("Expected line: {}", line.iter().map(|mov| game.board.format_move(*mov)).join(" "));
+    let win_rate = game.mcts.win_rate();
     game.placebit(best_move);
-    println!("Computer evaluation: {}", evaluation(game.evaluation()));
-    check_game_end(&game);
+    let eval = match (game.engine(), game.solver.max_depth(), weak) {
+        // Too big a board to solve
+        (Engine::Mcts, _, _) => format!("Won {:.0}% of playouts", win_rate.unwrap_or(0.0) * 100.0),
+        // Solving to the end would take much longer than the move did
+        (_, Some(depth), _) => game.evaluation_to_depth(depth).to_string(),
+        (_, None, true) => outcome(game.board.player(), game.weak_solve()),
+        (_, None, false) => game.evaluation().to_string(),
+    };
+    println!("Computer evaluation: {}", eval);
+    check_game_end(game);
     game.board.print(false);
 }
 
+const USAGE: &str = "Usage: client <xo|c4> [width] [height] [row] [depth]";
+
 fn main() {
-    let game_kind = env::args().nth(1).unwrap();
-    let args: Vec<u32> = env::args()
-        .skip(2)
-        .map(|a| a.parse::<u32>()
-             .expect("First three arguments need to numbers"))
-        .collect();
+    let game_kind = env::args().nth(1).unwrap_or_default();
+    let args: Vec<u32> = match env::args().skip(2).map(|a| a.parse::<u32>()).collect() {
+        Ok(args) => args,
+        Err(_) => {
+            println!("The board size, row and depth need to be numbers\n{}", USAGE);
+            exit(1);
+        },
+    };
     // println!("{:#?}", args);
-    let mut rl = Editor::<()>::new();
-    // let mut game = solver::Game::new_xo(args[0], args[1], args[2]);
-    let mut game = match game_kind.as_str() {
-        "xo" => solver::Game::new_xo(args[0], args[1], args[2]),
-        "c4" => solver::Game::new_connect_four(args[0], args[1], args[2]),
-        _ => panic!()
+    let mut config = match game_kind.as_str() {
+        "xo" => GameConfig::xo(),
+        "c4" => GameConfig::connect_four(),
+        _ => {
+            println!("{}", USAGE);
+            exit(1);
+        },
+    };
+    // Anything left out stays as the game's usual size
+    if let Some(width) = args.first() { config = config.width(*width); }
+    if let Some(height) = args.get(1) { config = config.height(*height); }
+    if let Some(row) = args.get(2) { config = config.row(*row); }
+
+    // Only use a wider bitboard than the board needs, since they're slower
+    let started = match config.bits() {
+        ..=64 => config.build::<u64>().map(|game| play(game, &args)),
+        65..=128 => config.build::<u128>().map(|game| play(game, &args)),
+        _ => config.build::<Bitboard256>().map(|game| play(game, &args)),
     };
+    if let Err(error) = started {
+        println!("{}", error);
+        exit(1);
+    }
+}
+
+fn play<B: Bitboard>(mut game: Game<BoardKind, B>, args: &[u32]) {
+    let mut rl = Editor::<()>::new();
+    // An optional fourth number limits how far ahead the computer looks
+    game.solver.set_max_depth(args.get(3).copied());
+    // Searching bigger boards than Connect Four's properly takes far too long
+    if game.board.moves_left() > 42 {
+        game.set_engine(Engine::Mcts);
+    }
+    game.solver.set_progress_callback(Some(Box::new(|stats, _| {
+        eprint!("\rDepth {}, {} nodes/s", stats.depth, stats.nodes_per_second());
+        io::stderr().flush().unwrap();
+    })));
+
+    let mut weak = false;
 
     if let Ok(answer) = rl.readline("X or O? ") {
         if answer.to_lowercase() == "o" {
-            cpuplay(&mut game)
+            cpuplay(&mut game, weak)
         }
     } else {
         exit(1);
@@ -69,23 +126,96 @@ fn main() {
         let readline = rl.readline(">> ");
 
         match readline {
-            Ok(line) => {
-                let mov: Move;
-                if game_kind.as_str() == "xo" {
-                    let pos: (u32, u32) = (line.splitn(2, '-') // converts "m-n" to (m, n) using rust magic
-                        .map(|num| num.parse::<u32>().unwrap())
-                        .collect_tuple())
-                        .unwrap();
-                    mov = game.pos_to_move(pos);
+            Ok(line) if line == "analyse" => {
+                for (mov, eval) in game.analyse_moves() {
+                    println!("{}: {}", game.board.format_move(mov), eval);
+                }
+            },
+            Ok(line) if line.starts_with("save ") => {
+                let path = line.trim_start_matches("save ").trim();
+                match File::create(path).and_then(|file| game.save_transpositions(BufWriter::new(file))) {
+                    Ok(()) => println!("Saved the transposition table"),
+                    Err(e) => println!("Couldn't save the transposition table: {}", e),
+                }
+            },
+            Ok(line) if line.starts_with("load ") => {
+                let path = line.trim_start_matches("load ").trim();
+                match File::open(path).and_then(|file| game.load_transpositions(BufReader::new(file))) {
+                    Ok(()) => println!("Loaded the transposition table"),
+                    Err(e) => println!("Couldn't load the transposition table: {}", e),
+                }
+            },
+            Ok(line) if line.starts_with("book ") => {
+                let path = line.trim_start_matches("book ").trim();
+                match File::open(path).and_then(|file| OpeningBook::read_from(BufReader::new(file))) {
+                    Ok(book) if book.matches(&game.board) => {
+                        println!("Loaded {} positions", book.len());
+                        game.solver.set_opening_book(Some(book));
+                    },
+                    Ok(_) => println!("That book is for a different game"),
+                    Err(e) => println!("Couldn't load the book: {}", e),
+                }
+            },
+            Ok(line) if line.starts_with("difficulty ") => {
+                let difficulty = match line.trim_start_matches("difficulty ").trim() {
+                    "easy" => Difficulty::easy(),
+                    "medium" => Difficulty::medium(),
+                    "hard" => Difficulty::hard(),
+                    "perfect" => Difficulty::perfect(),
+                    _ => {
+                        println!("Difficulty can be easy, medium, hard or perfect");
+                        continue;
+                    },
+                };
+                game.solver.set_difficulty(difficulty);
+            },
+            Ok(line) if line == "mcts" || line == "negamax" => {
+                game.set_engine(match line.as_str() {
+                    "mcts" => Engine::Mcts,
+                    _ => Engine::Negamax,
+                });
+                println!("Using {}", line);
+            },
+            // Takes back the player's last move and the computer's reply to it
+            Ok(line) if line == "undo" => {
+                if game.ply() < 2 {
+                    println!("Nothing to undo");
                 } else {
-                    mov = line.parse().unwrap();
+                    game.undo();
+                    game.undo();
+                    game.board.print(false);
                 }
-                if game.can_play(mov) {
-                    game.placebit(mov);
-                    check_game_end(&game);
-                    cpuplay(&mut game);
+            },
+            Ok(line) if line == "redo" => {
+                if game.redo().is_none() {
+                    println!("Nothing to redo");
                 } else {
-                    println!("you can't play that")
+                    game.redo();
+                    game.board.print(false);
+                }
+            },
+            Ok(line) if line == "weak" || line == "strong" => {
+                weak = line == "weak";
+                println!("Using {} solving", line);
+            },
+            Ok(line) => {
+                // Cells are written row-column, anything else is up to the game
+                let cell = line.split_once('-')
+                    .and_then(|(row, column)| Some((row.trim().parse().ok()?, column.trim().parse().ok()?)));
+                let played = match (cell, game.board.parse_move(&line)) {
+                    (Some(pos), _) => game.try_place(pos),
+                    (None, Some(mov)) => game.try_play(mov),
+                    (None, None) => {
+                        println!("that isn't a move");
+                        continue;
+                    },
+                };
+                match played {
+                    Ok(()) => {
+                        check_game_end(&game);
+                        cpuplay(&mut game, weak);
+                    },
+                    Err(error) => println!("{}", error),
                 }
             },
             Err(_) => {
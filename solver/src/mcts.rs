@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+use arrayvec::ArrayVec;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rayon::prelude::*;
+use crate::{Bitboard, Board, CancelToken, GameRules, Move, Player, SearchStats};
+
+const DEFAULT_ITERATIONS: u32 = 10_000;
+
+struct Node {
+    // The move that led here, 0 for the root
+    mov: Move,
+    parent: usize,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    // Playouts through here, and how many were won by whoever made the move that led
+    // here, with draws counting as half a win
+    visits: f64,
+    wins: f64,
+}
+
+impl Node {
+    fn new<R: GameRules, B: Bitboard>(mov: Move, parent: usize, board: &Board<R, B>) -> Node {
+        Node {
+            mov,
+            parent,
+            children: Vec::new(),
+            untried: match board.over() {
+                true => Vec::new(),
+                false => board.generate_moves().collect(),
+            },
+            visits: 0.0,
+            wins: 0.0,
+        }
+    }
+}
+
+// Plays random moves (but always takes a win) to the end of the game, scoring 1 if
+// the player won, 0.5 for a draw and 0 if they lost
+fn playout<R: GameRules, B: Bitboard>(board: &Board<R, B>, player: Player, seed: u64) -> f64 {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut board = board.clone();
+    while !board.over() {
+        let winning = board.reachable(board.winning_cells(board.player) & board.playable_cells());
+        let moves: ArrayVec<Move, 256> = board.generate_moves()
+            .filter(|mov| winning.is_empty() || !(board.move_cell(*mov) & winning).is_empty())
+            .collect();
+        board.placebit(moves[rng.gen_range(0..moves.len())]);
+    }
+
+    if board.has_won(player) {
+        1.0
+    } else if board.has_won(!player) {
+        0.0
+    } else {
+        0.5
+    }
+}
+
+// Monte Carlo tree search with UCT, for boards too big for the solver to search
+// properly. Rather than scoring positions it plays lots of random games, and spends
+// more of them on the moves that win most often.
+pub struct Mcts {
+    iterations: Option<u32>,
+    time_limit: Option<Duration>,
+    playouts: usize,
+    // How much UCT favours moves it knows little about over ones that look good
+    exploration: f64,
+    seed: u64,
+    last_stats: SearchStats,
+    win_rate: Option<f64>,
+    principal_variation: Vec<Move>,
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Mcts {
+            iterations: Some(DEFAULT_ITERATIONS),
+            time_limit: None,
+            playouts: rayon::current_num_threads(),
+            exploration: std::f64::consts::SQRT_2,
+            seed: 0,
+            last_stats: SearchStats::default(),
+            win_rate: None,
+            principal_variation: Vec::new(),
+        }
+    }
+}
+
+impl Mcts {
+    // The search stops at whichever limit it reaches first. With neither it stops
+    // after the default number of iterations.
+    pub fn set_iterations(&mut self, iterations: Option<u32>) {
+        self.iterations = iterations;
+    }
+
+    pub fn set_time_limit(&mut self, time_limit: Option<Duration>) {
+        self.time_limit = time_limit;
+    }
+
+    // How many random games are played, in parallel, from each new node
+    pub fn set_playouts(&mut self, playouts: usize) {
+        self.playouts = playouts.max(1);
+    }
+
+    pub fn set_exploration(&mut self, exploration: f64) {
+        self.exploration = exploration;
+    }
+
+    // The same seed always gives the same moves with the same limits (apart from time)
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    // Nodes are playouts, and depth is how deep the tree got
+    pub fn stats(&self) -> SearchStats {
+        self.last_stats
+    }
+
+    // The fraction of playouts through the chosen move that the player who was
+    // to move won (counting draws as half), from the last search
+    pub fn win_rate(&self) -> Option<f64> {
+        self.win_rate
+    }
+
+    // The most visited line from the last search, starting with the chosen move
+    pub fn principal_variation(&self) -> Vec<Move> {
+        self.principal_variation.clone()
+    }
+
+    // The child the search should go down next, balancing how well it's done so far
+    // against how little it's been tried
+    fn select(&self, nodes: &[Node], node: usize) -> usize {
+        let log_visits = nodes[node].visits.ln();
+        let uct = |child: &usize| {
+            let child = &nodes[*child];
+            child.wins / child.visits + self.exploration * (log_visits / child.visits).sqrt()
+        };
+        *nodes[node].children.iter()
+            .max_by(|a, b| uct(a).total_cmp(&uct(b)))
+            .expect("Selected a node with no children")
+    }
+
+    pub(crate) fn best_move<R: GameRules, B: Bitboard>(&mut self, board: &Board<R, B>, budget: Option<Duration>, cancel: &CancelToken) -> Move {
+        let started = Instant::now();
+        let time_limit = match (self.time_limit, budget) {
+            (Some(limit), Some(budget)) => Some(limit.min(budget)),
+            (limit, budget) => limit.or(budget),
+        };
+        let iterations = match time_limit {
+            None => Some(self.iterations.unwrap_or(DEFAULT_ITERATIONS)),
+            Some(_) => self.iterations,
+        };
+
+        let mut nodes = vec![Node::new(0, 0, board)];
+        let mut iteration = 0;
+        let mut max_depth = 0;
+        while iterations.is_none_or(|iterations| iteration < iterations) &&
+            time_limit.is_none_or(|limit| started.elapsed() < limit) &&
+            !cancel.is_cancelled() {
+            // Go down the tree until there's a move that hasn't been tried yet
+            let mut board = board.clone();
+            let mut node = 0;
+            let mut depth = 0;
+            while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+                node = self.select(&nodes, node);
+                board.placebit(nodes[node].mov);
+                depth += 1;
+            }
+
+            if let Some(mov) = nodes[node].untried.pop() {
+                board.placebit(mov);
+                nodes.push(Node::new(mov, node, &board));
+                let child = nodes.len() - 1;
+                nodes[node].children.push(child);
+                node = child;
+                depth += 1;
+            }
+            max_depth = max_depth.max(depth);
+
+            let mover = !board.player;
+            let seed = self.seed ^ ((iteration as u64) << 32);
+            let mut wins: f64 = (0..self.playouts as u64).into_par_iter()
+                .map(|playout_index| playout(&board, mover, seed ^ playout_index))
+                .sum();
+
+            // Each node's wins are for the player who moved into it, so flip them on the way up
+            let playouts = self.playouts as f64;
+            loop {
+                nodes[node].visits += playouts;
+                nodes[node].wins += wins;
+                if node == 0 { break; }
+                wins = playouts - wins;
+                node = nodes[node].parent;
+            }
+            iteration += 1;
+        }
+
+        let most_visited = |node: usize| nodes[node].children.iter().copied()
+            .max_by(|a, b| nodes[*a].visits.total_cmp(&nodes[*b].visits));
+        self.principal_variation.clear();
+        let mut node = 0;
+        while let Some(child) = most_visited(node) {
+            self.principal_variation.push(nodes[child].mov);
+            node = child;
+        }
+
+        self.last_stats = SearchStats {
+            nodes: nodes[0].visits as u64,
+            depth: max_depth,
+            elapsed: started.elapsed(),
+            ..SearchStats::default()
+        };
+        match most_visited(0) {
+            Some(best) => {
+                self.win_rate = Some(nodes[best].wins / nodes[best].visits);
+                nodes[best].mov
+            },
+            // Not even one iteration
+            None => {
+                self.win_rate = None;
+                board.generate_moves().next().expect("No move was chosen")
+            },
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{Bitboard, Board, GameRules, Move, Player};
+
+// Stands for a position that can't be proven (or disproven) however much is searched
+const INFINITE: u32 = u32::MAX;
+
+// How a player forces a win from a position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofTree {
+    // The game is over and the player has won
+    Won,
+    // The player makes this move
+    Attack(Move, Box<ProofTree>),
+    // Every move the opponent can make, and how the player still wins after it
+    Defend(Vec<(Move, ProofTree)>),
+}
+
+impl ProofTree {
+    // Positions in the proof, counting the one it starts from
+    pub fn size(&self) -> usize {
+        1 + match self {
+            ProofTree::Won => 0,
+            ProofTree::Attack(_, tree) => tree.size(),
+            ProofTree::Defend(replies) => replies.iter().map(|(_, tree)| tree.size()).sum(),
+        }
+    }
+
+    // Checks the proof really is one for the player from the position, i.e. that it
+    // only makes legal moves and covers every reply
+    pub fn verify<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, player: Player) -> bool {
+        match self {
+            ProofTree::Won => board.has_won(player),
+            ProofTree::Attack(mov, tree) => {
+                if board.over() || board.player != player || !board.can_play(*mov) { return false; }
+                let mut board = board.clone();
+                board.placebit(*mov);
+                tree.verify(&board, player)
+            },
+            ProofTree::Defend(replies) => {
+                if board.over() || board.player == player { return false; }
+                let mut moves: Vec<Move> = board.generate_moves().collect();
+                let mut replied: Vec<Move> = replies.iter().map(|(mov, _)| *mov).collect();
+                moves.sort_unstable();
+                replied.sort_unstable();
+                moves == replied && replies.iter().all(|(mov, tree)| {
+                    let mut board = board.clone();
+                    board.placebit(*mov);
+                    tree.verify(&board, player)
+                })
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Proof {
+    Win(ProofTree),
+    // The player can't force a win: the best they can do is draw or lose
+    NoWin,
+    // Ran out of nodes before finding out
+    Unknown,
+}
+
+struct Node {
+    // The move that led here, 0 for the root
+    mov: Move,
+    parent: usize,
+    children: Vec<usize>,
+    // How many more positions would have to be proven to prove or disprove this one
+    proof: u32,
+    disproof: u32,
+    // A move that wins straight away, found without expanding the node
+    winning_move: Option<Move>,
+}
+
+// Best first proof-number search, which always expands the position that would do
+// most towards proving or disproving the whole tree. Much better than alpha-beta at
+// finding forced wins in wide trees, since it goes for the moves with fewest replies.
+struct ProofSearch {
+    player: Player,
+    nodes: Vec<Node>,
+}
+
+impl ProofSearch {
+    fn new_node<R: GameRules, B: Bitboard>(&mut self, mov: Move, parent: usize, board: &Board<R, B>) -> usize {
+        let (proof, disproof, winning_move) = if board.over() {
+            match board.has_won(self.player) {
+                true => (0, INFINITE, None),
+                false => (INFINITE, 0, None),
+            }
+        } else {
+            // Only trust winning_cells if there's a move that actually plays in one of them
+            let winning = board.winning_cells(board.player) & board.playable_cells();
+            let winning_move = match winning.is_empty() {
+                true => None,
+                false => board.generate_moves().find(|mov| !(board.move_cell(*mov) & winning).is_empty()),
+            };
+            match (winning_move, board.player == self.player) {
+                (Some(mov), true) => (0, INFINITE, Some(mov)),
+                (Some(_), false) => (INFINITE, 0, None),
+                (None, true) => (1, 1, None),
+                // The fewer replies the opponent has the easier it should be to prove
+                (None, false) => (board.generate_moves().count() as u32, 1, None),
+            }
+        };
+
+        self.nodes.push(Node { mov, parent, children: Vec::new(), proof, disproof, winning_move });
+        self.nodes.len() - 1
+    }
+
+    // Goes down the tree to the unexpanded node that matters most, playing the moves
+    // on the board on the way
+    fn most_proving<R: GameRules, B: Bitboard>(&self, board: &mut Board<R, B>) -> usize {
+        let mut node = 0;
+        while !self.nodes[node].children.is_empty() {
+            let attacking = board.player == self.player;
+            node = *self.nodes[node].children.iter()
+                .min_by_key(|child| match attacking {
+                    true => self.nodes[**child].proof,
+                    false => self.nodes[**child].disproof,
+                })
+                .unwrap();
+            board.placebit(self.nodes[node].mov);
+        }
+        node
+    }
+
+    fn expand<R: GameRules, B: Bitboard>(&mut self, node: usize, board: &Board<R, B>) {
+        let mut children = Vec::new();
+        for mov in board.generate_moves() {
+            let mut child = board.clone();
+            child.placebit(mov);
+            children.push(self.new_node(mov, node, &child));
+        }
+        self.nodes[node].children = children;
+    }
+
+    // Recalculates the numbers of the node and everything above it, given its new children.
+    // Takes the board at the node, and leaves it back at the root.
+    fn update_ancestors<R: GameRules, B: Bitboard>(&mut self, mut node: usize, board: &mut Board<R, B>) {
+        loop {
+            let children = &self.nodes[node].children;
+            let proofs = children.iter().map(|child| self.nodes[*child].proof);
+            let disproofs = children.iter().map(|child| self.nodes[*child].disproof);
+            // The player needs one move that works, but has to answer every reply
+            let (proof, disproof) = match board.player == self.player {
+                true => (proofs.min().unwrap(), disproofs.fold(0, u32::saturating_add)),
+                false => (proofs.fold(0, u32::saturating_add), disproofs.min().unwrap()),
+            };
+            self.nodes[node].proof = proof;
+            self.nodes[node].disproof = disproof;
+
+            if node == 0 { break; }
+            board.undo_move(self.nodes[node].mov);
+            node = self.nodes[node].parent;
+        }
+    }
+
+    fn tree<R: GameRules, B: Bitboard>(&self, node: usize, board: &Board<R, B>) -> ProofTree {
+        let node = &self.nodes[node];
+        if board.over() { return ProofTree::Won; }
+        if let Some(mov) = node.winning_move {
+            return ProofTree::Attack(mov, Box::new(ProofTree::Won));
+        }
+
+        let next = |child: usize| {
+            let mut board = board.clone();
+            board.placebit(self.nodes[child].mov);
+            (self.nodes[child].mov, self.tree(child, &board))
+        };
+        match board.player == self.player {
+            true => {
+                let child = *node.children.iter().find(|child| self.nodes[**child].proof == 0).unwrap();
+                let (mov, tree) = next(child);
+                ProofTree::Attack(mov, Box::new(tree))
+            },
+            false => ProofTree::Defend(node.children.iter().map(|child| next(*child)).collect()),
+        }
+    }
+}
+
+// Finds out whether the player can force a win from the position, giving up after
+// looking at this many positions
+pub(crate) fn prove_win<R: GameRules, B: Bitboard>(board: &Board<R, B>, player: Player, max_nodes: usize) -> Proof {
+    let mut search = ProofSearch { player, nodes: Vec::new() };
+    search.new_node(0, 0, board);
+
+    let mut board = board.clone();
+    while search.nodes[0].proof != 0 && search.nodes[0].disproof != 0 {
+        if search.nodes.len() >= max_nodes { return Proof::Unknown; }
+
+        let node = search.most_proving(&mut board);
+        search.expand(node, &board);
+        search.update_ancestors(node, &mut board);
+    }
+
+    match search.nodes[0].proof {
+        0 => Proof::Win(search.tree(0, &board)),
+        _ => Proof::NoWin,
+    }
+}
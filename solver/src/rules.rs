@@ -0,0 +1,385 @@
+use std::{error::Error, fmt};
+use arrayvec::ArrayVec;
+use crate::{Bitboard, Board, Move, Moves, Player, Transform};
+
+// Why a move can't be played
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    Occupied,
+    OutOfBounds,
+    ColumnFull,
+    GameOver,
+    // Like placing a piece in a cell in a game where moves are columns
+    WrongMoveKind,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Occupied => write!(f, "That cell is already taken"),
+            MoveError::OutOfBounds => write!(f, "That's off the board"),
+            MoveError::ColumnFull => write!(f, "That column is full"),
+            MoveError::GameOver => write!(f, "The game is over"),
+            MoveError::WrongMoveKind => write!(f, "This game doesn't have moves like that"),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+// Everything that's different between the games the solver can play. Pieces are always
+// bits in the board's bitboards (see Board::get_index for where each cell is), but what
+// a move is depends on the game: for noughts and crosses it's the cell's bit index, and
+// for Connect Four it's the column number.
+//
+// A Move is always a plain u64 rather than a type each game picks, because the
+// transposition table, opening book and tablebase store moves as move_index's u8, and
+// Moves is either a bitboard of cells or a short list, which covers both kinds of game.
+// Playing a move always puts one piece in played_cell, so there are no apply or undo
+// hooks either: games where moves capture or move pieces don't fit on these boards.
+//
+// The cell-based methods default to noughts and crosses, where a move is the bit index
+// of any empty cell, so games like that only need id and whatever they do differently.
+// Games with other kinds of move, like ConnectFour, override all of them.
+//
+// Methods take boards with any rules so that rules can hand their boards on to other
+// rules, like BoardKind does, and any size of bitboard.
+pub trait GameRules: Clone + Send + Sync + 'static {
+    // Different for every game, and saved in files so they aren't used for the wrong game
+    fn id(&self) -> u8;
+
+    // Every way of flipping or rotating a board this size that leaves the game
+    // unchanged, apart from doing nothing
+    fn symmetries(&self, width: u32, height: u32) -> Vec<Transform> {
+        let mut transforms: Vec<Transform> = vec![
+            |r, c, _, w| (r, w - c), // Mirrored left to right
+            |r, c, h, _| (h - r, c), // Mirrored top to bottom
+            |r, c, h, w| (h - r, w - c), // Rotated by 180°
+        ];
+
+        if width == height {
+            transforms.push(|r, c, _, _| (c, r)); // Mirrored along the main diagonal
+            transforms.push(|r, c, h, w| (w - c, h - r)); // Mirrored along the other diagonal
+            transforms.push(|r, c, h, _| (c, h - r)); // Rotated by 90°
+            transforms.push(|r, c, _, w| (w - c, r)); // Rotated by 270°
+        }
+        transforms
+    }
+
+    // Empty cells the player to move could put a piece in right now. Cells that no
+    // generated move plays in are allowed, and the search checks for them.
+    fn playable_cells<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> B {
+        !(board.bitboards[0] | board.bitboards[1]) & board.board_mask()
+    }
+
+    fn generate_moves<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Moves<B> {
+        Moves::XOMoves(self.playable_cells(board), 0, board.used_bits())
+    }
+
+    fn can_play<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> bool {
+        mov < board.used_bits() as Move && !board.over() &&
+            !(self.playable_cells(board) & self.move_cell(board, mov)).is_empty()
+    }
+
+    // Why the move can't be played, if it can't. Whether the game is over is checked first.
+    fn check_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> Result<(), MoveError> {
+        // The spare bits at the top of the columns aren't cells either
+        if mov >= board.used_bits() as Move || (B::bit(mov as u32) & board.board_mask()).is_empty() {
+            return Err(MoveError::OutOfBounds);
+        }
+        match (self.playable_cells(board) & B::bit(mov as u32)).is_empty() {
+            true => Err(MoveError::Occupied),
+            false => Ok(()),
+        }
+    }
+
+    // The move that puts a piece in the cell, for games where moves are cells
+    fn cell_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, row: u32, column: u32) -> Result<Move, MoveError> {
+        match row < board.height() && column < board.width() {
+            true => Ok(board.get_index(row, column) as Move),
+            false => Err(MoveError::OutOfBounds),
+        }
+    }
+
+    // The cell the move would put a piece in
+    fn move_cell<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, mov: Move) -> B {
+        B::bit(mov as u32)
+    }
+
+    // The cell the move put a piece in, on the board after it was played
+    fn played_cell<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, mov: Move) -> B {
+        B::bit(mov as u32)
+    }
+
+    // A small number for each move, small enough to store in the transposition table
+    fn move_index<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, mov: Move) -> u8 {
+        mov as u8
+    }
+
+    fn index_move<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, index: u8) -> Move {
+        index as Move
+    }
+
+    // Moves the move the same way the permutation (see symmetries) moves the cells
+    fn transform_move<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, permutation: &[u32], mov: Move) -> Move {
+        permutation[mov as usize] as Move
+    }
+
+    // How close to the middle of the board the move is, higher is closer
+    fn centrality<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> i32 {
+        let index = mov as u32;
+        let (row, column) = (index % (board.height() + 1), index / (board.height() + 1));
+        -distance(row, board.height()) - distance(column, board.width())
+    }
+
+    // Moves are written as row-column, counting from the top left
+    fn format_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> String {
+        let index = mov as u32;
+        let height = board.height() + 1;
+        format!("{}-{}", height - 2 - index % height, index / height)
+    }
+
+    fn parse_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, text: &str) -> Option<Move> {
+        let (row, column) = text.trim().split_once('-')?;
+        let (row, column) = (row.parse().ok()?, column.parse().ok()?);
+        (row < board.height() && column < board.width()).then(|| board.get_index(row, column) as Move)
+    }
+
+    // Whether the player has won. By default that's by getting `row` in a row.
+    fn has_won<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, player: Player) -> bool {
+        board.has_line(player)
+    }
+
+    // Empty cells that would win the game for the player, whether or not they can be
+    // played in yet. The search uses them to find wins and forced moves without
+    // searching, so rules where that doesn't work should return B::ZERO.
+    fn winning_cells<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, player: Player) -> B {
+        board.line_cells(player)
+    }
+
+    // Cells the player to move shouldn't play in, given the cells the opponent would
+    // win with, because they make the opponent's cells playable
+    fn dangerous_cells<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, _threats: B) -> B {
+        B::ZERO
+    }
+
+    fn render<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> String {
+        board.grid()
+    }
+
+    // The widest board the rules can handle, if there's a limit
+    fn max_width(&self) -> Option<u32> {
+        None
+    }
+}
+
+// How far the position is from the middle of a line this long
+fn distance(position: u32, size: u32) -> i32 {
+    (2 * position as i32 - (size as i32 - 1)).abs()
+}
+
+// Noughts and crosses, or any m,n,k-game: pieces go in any empty cell. That's what
+// GameRules does by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoughtsAndCrosses;
+
+impl GameRules for NoughtsAndCrosses {
+    fn id(&self) -> u8 {
+        0
+    }
+}
+
+// Connect Four: pieces drop to the bottom of the column they're played in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectFour;
+
+impl ConnectFour {
+    fn column_mask<R: GameRules, B: Bitboard>(board: &Board<R, B>, column: Move) -> B {
+        B::low_bits(board.height()) << (column as u32 * (board.height() + 1))
+    }
+}
+
+impl GameRules for ConnectFour {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn symmetries(&self, _width: u32, _height: u32) -> Vec<Transform> {
+        // Gravity rules out anything but mirroring
+        vec![|r, c, _, w| (r, w - c)]
+    }
+
+    fn playable_cells<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> B {
+        let occupied = board.bitboards[0] | board.bitboards[1];
+        occupied.wrapping_add(board.bottom_mask()) & board.board_mask()
+    }
+
+    fn generate_moves<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Moves<B> {
+        let moves: ArrayVec<Move, 16> = (0..board.width() as Move)
+            .filter(|column| self.can_play(board, *column))
+            .collect();
+        Moves::C4Moves(moves, 0)
+    }
+
+    fn can_play<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> bool {
+        mov < board.width() as Move && !(self.playable_cells(board) & Self::column_mask(board, mov)).is_empty()
+    }
+
+    fn check_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> Result<(), MoveError> {
+        if mov >= board.width() as Move {
+            return Err(MoveError::OutOfBounds);
+        }
+        match self.can_play(board, mov) {
+            true => Ok(()),
+            false => Err(MoveError::ColumnFull),
+        }
+    }
+
+    // Moves are columns, not cells
+    fn cell_move<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, _row: u32, _column: u32) -> Result<Move, MoveError> {
+        Err(MoveError::WrongMoveKind)
+    }
+
+    fn move_cell<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> B {
+        self.playable_cells(board) & Self::column_mask(board, mov)
+    }
+
+    fn played_cell<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> B {
+        let column = (board.bitboards[0] | board.bitboards[1]) & Self::column_mask(board, mov);
+        B::bit(B::BITS - 1 - column.leading_zeros())
+    }
+
+    fn move_index<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, mov: Move) -> u8 {
+        mov as u8
+    }
+
+    fn index_move<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, index: u8) -> Move {
+        index as Move
+    }
+
+    fn transform_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, permutation: &[u32], mov: Move) -> Move {
+        let height = board.height() + 1;
+        (permutation[(mov * height as u64) as usize] / height) as Move
+    }
+
+    fn centrality<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> i32 {
+        -distance(mov as u32, board.width())
+    }
+
+    fn format_move<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, mov: Move) -> String {
+        mov.to_string()
+    }
+
+    fn parse_move<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, text: &str) -> Option<Move> {
+        text.trim().parse().ok()
+    }
+
+    // As many columns as Moves::C4Moves has room for
+    fn max_width(&self) -> Option<u32> {
+        Some(16)
+    }
+
+    // Playing underneath a cell the opponent wants lets them play there
+    fn dangerous_cells<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, threats: B) -> B {
+        threats >> 1
+    }
+}
+
+// The games that come with the solver, chosen when the game starts rather than at compile time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardKind {
+    XOBoard,
+    C4Board,
+}
+
+// Hands everything on to the rules of the game being played
+macro_rules! dispatch {
+    ($kind:expr, $rules:ident => $call:expr) => {
+        match $kind {
+            BoardKind::XOBoard => { let $rules = NoughtsAndCrosses; $call },
+            BoardKind::C4Board => { let $rules = ConnectFour; $call },
+        }
+    };
+}
+
+impl GameRules for BoardKind {
+    fn id(&self) -> u8 {
+        dispatch!(self, rules => rules.id())
+    }
+
+    fn symmetries(&self, width: u32, height: u32) -> Vec<Transform> {
+        dispatch!(self, rules => rules.symmetries(width, height))
+    }
+
+    fn playable_cells<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> B {
+        dispatch!(self, rules => rules.playable_cells(board))
+    }
+
+    fn generate_moves<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Moves<B> {
+        dispatch!(self, rules => rules.generate_moves(board))
+    }
+
+    fn can_play<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> bool {
+        dispatch!(self, rules => rules.can_play(board, mov))
+    }
+
+    fn check_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> Result<(), MoveError> {
+        dispatch!(self, rules => rules.check_move(board, mov))
+    }
+
+    fn cell_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, row: u32, column: u32) -> Result<Move, MoveError> {
+        dispatch!(self, rules => rules.cell_move(board, row, column))
+    }
+
+    fn move_cell<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> B {
+        dispatch!(self, rules => rules.move_cell(board, mov))
+    }
+
+    fn played_cell<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> B {
+        dispatch!(self, rules => rules.played_cell(board, mov))
+    }
+
+    fn move_index<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> u8 {
+        dispatch!(self, rules => rules.move_index(board, mov))
+    }
+
+    fn index_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, index: u8) -> Move {
+        dispatch!(self, rules => rules.index_move(board, index))
+    }
+
+    fn transform_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, permutation: &[u32], mov: Move) -> Move {
+        dispatch!(self, rules => rules.transform_move(board, permutation, mov))
+    }
+
+    fn centrality<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> i32 {
+        dispatch!(self, rules => rules.centrality(board, mov))
+    }
+
+    fn format_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, mov: Move) -> String {
+        dispatch!(self, rules => rules.format_move(board, mov))
+    }
+
+    fn parse_move<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, text: &str) -> Option<Move> {
+        dispatch!(self, rules => rules.parse_move(board, text))
+    }
+
+    fn has_won<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, player: Player) -> bool {
+        dispatch!(self, rules => rules.has_won(board, player))
+    }
+
+    fn winning_cells<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, player: Player) -> B {
+        dispatch!(self, rules => rules.winning_cells(board, player))
+    }
+
+    fn dangerous_cells<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, threats: B) -> B {
+        dispatch!(self, rules => rules.dangerous_cells(board, threats))
+    }
+
+    fn render<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> String {
+        dispatch!(self, rules => rules.render(board))
+    }
+
+    fn max_width(&self) -> Option<u32> {
+        dispatch!(self, rules => rules.max_width())
+    }
+}
@@ -1,17 +1,44 @@
-use std::{cmp::{max, min}, ops::Not, sync::Arc};
+use std::{cmp::{max, min}, fmt, io::{self, Read, Write}, ops::Not, sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}}, time::{Duration, Instant}};
 use arrayvec::ArrayVec;
-use dashmap::DashMap;
-use fxhash::FxBuildHasher;
+use rand::{rngs::SmallRng, SeedableRng};
 use rayon::prelude::*;
+use ordering::OrderingTables;
+use transpositions::{Score, ScoreKind, TranspositionTable};
+
+pub use bitboard::{Bitboard, Bitboard256, WideBitboard};
+pub use book::OpeningBook;
+pub use config::{ConfigError, GameConfig};
+pub use difficulty::Difficulty;
+pub use mcts::Mcts;
+pub use tablebase::Tablebase;
+pub use ordering::MoveOrdering;
+pub use proof::{Proof, ProofTree};
+pub use rules::{BoardKind, ConnectFour, GameRules, MoveError, NoughtsAndCrosses};
+
+mod bitboard;
+mod book;
+mod config;
+mod difficulty;
+mod mcts;
+mod ordering;
+mod proof;
+mod rules;
+mod tablebase;
+mod transpositions;
 
-type Bitboard = u64; // Maximum board size is 7x8
 pub type Move = u64;
 
 const INFINITY: i32 = i32::MAX;
 const NEGINFINITY: i32 = i32::MIN + 1;
 const WIN_SCORE: i32 = INFINITY;
+// Any score further from zero than this is a forced win or loss rather than a guess
+const WIN_THRESHOLD: i32 = WIN_SCORE - 4096;
+// How many nodes to search between checks of the clock
+const CLOCK_POLL_NODES: u64 = 1024;
+// How often the progress callback is called
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
-type FxDashMap<K, V> = DashMap<K, V, FxBuildHasher>;
+const DEFAULT_TABLE_SIZE_MB: usize = 32;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Player {
@@ -30,26 +57,55 @@ impl Not for Player {
     }
 }
 
+// What a search found out about a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Evaluation {
+    // This player wins in this many plies with perfect play (0 if they already have)
+    Win { player: Player, plies: u32 },
+    Draw,
+    // Not searched to the end of the game, so only a guess. Positive is good for X.
+    Heuristic(i32),
+    // The search was stopped before it found anything
+    Unknown,
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Evaluation::Win { player, plies: 0 } => write!(f, "{:?} has won", player),
+            Evaluation::Win { player, plies } => {
+                // Only the winner's own moves count as turns
+                let turns = plies.div_ceil(2);
+                write!(f, "{:?} wins in {} turn{}", player, turns, if turns == 1 { "" } else { "s" })
+            },
+            Evaluation::Draw => write!(f, "Draw"),
+            Evaluation::Heuristic(score) => write!(f, "Unclear ({:+} for X)", score),
+            Evaluation::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
-pub enum Moves {
-    XOMoves(Move, u64, u8),
-    C4Moves(ArrayVec<Move, 10>, usize),
+pub enum Moves<B: Bitboard = u64> {
+    // The index of every set bit of a bitboard as a move of its own, as in noughts and crosses
+    XOMoves(B, u64, u8),
+    // A list of moves, like Connect Four's columns
+    C4Moves(ArrayVec<Move, 16>, usize),
 }
 
-impl Iterator for Moves {
+impl<B: Bitboard> Iterator for Moves<B> {
     type Item = Move;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             Moves::XOMoves(ref moves, ref mut pos, ref used_bits) => {
-                for i in *pos..*used_bits as u64 { // probably wrong, but it works
-                    let mov: Move = ((moves >> i) & 1) << i;
-                    if mov != 0 {
+                for i in *pos..*used_bits as u64 {
+                    if !(*moves & B::bit(i as u32)).is_empty() {
                         *pos = i + 1;
-                        return Some(mov);
+                        return Some(i);
                     }
                 }
-                return None
+                None
             },
             Moves::C4Moves(ref moves, ref mut pos) => {
                 if *pos < moves.len() {
@@ -57,74 +113,261 @@ impl Iterator for Moves {
                     *pos += 1;
                     return Some(mov);
                 }
-                return None
+                None
             },
         }
     }
 }
 
-fn generate_top_mask(width: u32, height: u32) -> Bitboard {
-    let mut top_mask = 0;
-    for i in 1..width + 2 {
-        top_mask |= 1 << ((height + 1) * i)
+fn generate_top_mask<B: Bitboard>(width: u32, height: u32) -> B {
+    let mut top_mask = B::ZERO;
+    for i in 1..=width {
+        // Shifting one less rather than shifting the whole mask afterwards, so boards
+        // that use the very top bit don't overflow
+        top_mask |= B::bit((height + 1) * i - 1)
     }
-    top_mask >> 1
+    top_mask
 }
 
-pub struct Game {
-    pub board: Board,
-    pub solver: Solver,
+// Takes a cell's row and column, and the last row and column, and gives where the cell moves to
+pub type Transform = fn(u32, u32, u32, u32) -> (u32, u32);
+
+// Turns the game's symmetries into permutations mapping every bit index to the index it moves to
+fn generate_symmetries(width: u32, height: u32, transforms: Vec<Transform>) -> Arc<Vec<Vec<u32>>> {
+    let (w, h) = (width - 1, height - 1);
+    let index = |row: u32, column: u32| height - 1 - row + column * (height + 1);
+    Arc::new(transforms.into_iter().map(|transform| {
+        // Bits outside the board stay where they are
+        let mut permutation: Vec<u32> = (0..width * (height + 1)).collect();
+        for row in 0..height {
+            for column in 0..width {
+                let (new_row, new_column) = transform(row, column, h, w);
+                permutation[index(row, column) as usize] = index(new_row, new_column);
+            }
+        }
+        permutation
+    }).collect())
+}
+
+// The cells that start a run of `length` pieces, going `step` bits at a time. The runs
+// double in length each time, so long runs only take a few shifts.
+fn runs<B: Bitboard>(bitboard: B, length: u32, step: u32) -> B {
+    let mut runs = bitboard;
+    let mut run = 1;
+    while run < length && !runs.is_empty() {
+        let extend = run.min(length - run);
+        runs &= runs.checked_shr(extend * step).unwrap_or(B::ZERO);
+        run += extend;
+    }
+    runs
+}
+
+// A random-looking number for each player and bit index. The hash of a position is all
+// of them for its pieces xored together, so it can be updated a piece at a time.
+// Always the same numbers, so hashes can be saved and compared between runs.
+fn zobrist(player: Player, index: u32) -> u64 {
+    // splitmix64
+    let mut z = (index as u64 * 2 + player as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Which engine chooses the computer's moves
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    // Alpha-beta search with the solver
+    #[default]
+    Negamax,
+    // Monte Carlo tree search, for boards too big to search properly
+    Mcts,
+}
+
+pub struct Game<R: GameRules = BoardKind, B: Bitboard = u64> {
+    pub board: Board<R, B>,
+    pub solver: Solver<R, B>,
+    pub mcts: Mcts,
+    engine: Engine,
+    // Moves played through the game (not straight on the board), and moves undone since
+    // the last one, most recently undone last
+    history: Vec<Move>,
+    undone: Vec<Move>,
 }
 
 impl Game {
     pub fn new_xo(width: u32, height: u32, row: u32) -> Game {
-        Game {
-            board: Board {
-                width,
-                height,
-                row,
-                bitboards: [0, 0],
-                player: Player::X,
-                top_mask: generate_top_mask(width, height),
-                used_bits: (width * (height + 1)) as u8,
-                kind: BoardKind::XOBoard,
-                col_tops: vec![0; width as usize]
-            },
-            solver: Solver::new(),
-        }
+        Game::with_rules(BoardKind::XOBoard, width, height, row)
     }
 
     pub fn new_connect_four(width: u32, height: u32, row: u32) -> Game {
+        Game::with_rules(BoardKind::C4Board, width, height, row)
+    }
+}
+
+impl<R: GameRules> Game<R> {
+    // A game with any rules, including ones from outside the crate
+    pub fn with_rules(rules: R, width: u32, height: u32, row: u32) -> Game<R> {
+        Game::new(rules, width, height, row)
+    }
+}
+
+impl<R: GameRules, B: Bitboard> Game<R, B> {
+    // A game on a bitboard of any size, for boards too big for a u64. Panics if the board
+    // doesn't make sense or doesn't fit (see GameConfig for when it doesn't).
+    pub fn new(rules: R, width: u32, height: u32, row: u32) -> Game<R, B> {
+        GameConfig::new(rules).width(width).height(height).row(row).build()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Only for boards that GameConfig has checked
+    pub(crate) fn unchecked(rules: R, width: u32, height: u32, row: u32) -> Game<R, B> {
+        let symmetries = generate_symmetries(width, height, rules.symmetries(width, height));
         Game {
             board: Board {
                 width,
                 height,
                 row,
-                bitboards: [0, 0],
+                bitboards: [B::ZERO; 2],
                 player: Player::X,
                 top_mask: generate_top_mask(width, height),
                 used_bits: (width * (height + 1)) as u8,
-                kind: BoardKind::C4Board,
-                col_tops: vec![0; width as usize]
+                hashes: (0..=symmetries.len()).map(|_| 0).collect(),
+                symmetries,
+                rules,
             },
             solver: Solver::new(),
+            mcts: Mcts::default(),
+            engine: Engine::default(),
+            history: Vec::new(),
+            undone: Vec::new(),
         }
     }
 
     pub fn place(&mut self, pos: (u32, u32)) {
-        self.board.placebit(self.pos_to_move(pos));
+        self.placebit(self.pos_to_move(pos));
     }
 
     pub fn placebit(&mut self, mov: Move) {
         self.board.placebit(mov);
+        self.record(mov);
+    }
+
+    // A new move means the undone ones can't be redone any more
+    fn record(&mut self, mov: Move) {
+        self.history.push(mov);
+        self.undone.clear();
+    }
+
+    // Takes back the last move, giving what it was, or None if there's nothing to undo
+    pub fn undo(&mut self) -> Option<Move> {
+        let mov = self.history.pop()?;
+        self.board.undo_move(mov);
+        self.undone.push(mov);
+        Some(mov)
+    }
+
+    // Plays the last undone move again
+    pub fn redo(&mut self) -> Option<Move> {
+        let mov = self.undone.pop()?;
+        self.board.placebit(mov);
+        self.history.push(mov);
+        Some(mov)
+    }
+
+    // Every move played so far, first first
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    // How many moves have been played
+    pub fn ply(&self) -> u32 {
+        self.history.len() as u32
+    }
+
+    // Like place, but checks the cell is on the board and empty first
+    pub fn try_place(&mut self, pos: (u32, u32)) -> Result<(), MoveError> {
+        if self.board.over() { return Err(MoveError::GameOver); }
+        let mov = self.board.rules.cell_move(&self.board, pos.0, pos.1)?;
+        self.try_play(mov)
+    }
+
+    // Like placebit, but checks the move can be played first
+    pub fn try_play(&mut self, mov: Move) -> Result<(), MoveError> {
+        self.board.try_play(mov)?;
+        self.record(mov);
+        Ok(())
     }
 
     pub fn pos_to_move(&self, pos: (u32, u32)) -> Move {
-        1 << self.board.get_index(pos.0, pos.1)
+        self.board.get_index(pos.0, pos.1) as Move
+    }
+
+    // Only makes sense for noughts and crosses, where moves are squares
+    pub fn move_to_pos(&self, mov: Move) -> (u32, u32) {
+        let index = mov as u32;
+        let height = self.board.height + 1;
+        (height - 2 - index % height, index / height)
+    }
+
+    // Only best_move, best_move_within, search_stats and principal_variation use the
+    // engine. Evaluating and analysing positions always use the solver.
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
     }
 
     pub fn best_move(&mut self) -> Move {
-        self.solver.best_move(&mut self.board)
+        match self.engine {
+            Engine::Negamax => self.solver.best_move(&mut self.board),
+            Engine::Mcts => self.mcts_move(None),
+        }
+    }
+
+    pub fn best_move_within(&mut self, budget: Duration) -> SearchResult {
+        match self.engine {
+            Engine::Negamax => self.solver.best_move_within(&self.board, budget),
+            Engine::Mcts => SearchResult {
+                best_move: self.mcts_move(Some(budget)),
+                depth: 0,
+                proven: false,
+                evaluation: Evaluation::Unknown,
+                stats: self.mcts.stats(),
+            },
+        }
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.solver.cancel_token()
+    }
+
+    // MCTS shares the solver's cancel token, so cancel_token() stops either engine
+    fn mcts_move(&mut self, budget: Option<Duration>) -> Move {
+        self.solver.cancel.reset();
+        self.mcts.best_move(&self.board, budget, &self.solver.cancel)
+    }
+
+    // Statistics from the last call to best_move, best_move_within or evaluation
+    pub fn search_stats(&self) -> SearchStats {
+        match self.engine {
+            Engine::Negamax => self.solver.stats(),
+            Engine::Mcts => self.mcts.stats(),
+        }
+    }
+
+    // The line of play expected from here, starting with the move the last search chose.
+    // Search (e.g. with best_move) from this position first.
+    pub fn principal_variation(&self) -> Vec<Move> {
+        match self.engine {
+            Engine::Negamax => self.solver.principal_variation(&self.board),
+            Engine::Mcts => self.mcts.principal_variation(),
+        }
     }
 
     pub fn can_play(&self, mov: Move) -> bool {
@@ -135,70 +378,137 @@ impl Game {
         self.board.render(false)
     }
 
-    // Doesn't return the raw score, but number of plies to win
-    // 0 means a draw, not a won position
-    pub fn evaluation(&mut self) -> i32 {
-        let player = match self.board.player {
-            Player::X => -1,
-            Player::O => 1,
-        };
+    // Solves the position to the end of the game, so the result is never a heuristic.
+    // If the search is cancelled the result is Unknown.
+    pub fn evaluation(&mut self) -> Evaluation {
+        self.solver.solve(&mut self.board)
+    }
 
-        let score = self.solver.negamax(&mut self.board, INFINITY, NEGINFINITY, INFINITY, player) * -player as i32;
-        if score == 0 { return 0; }
-        match score < 0 {
-            true => -WIN_SCORE - score,
-            false => WIN_SCORE - score,
-        }
+    // Like evaluation(), but only looks this many plies ahead and guesses with the
+    // evaluator past that, so it can be Evaluation::Heuristic
+    pub fn evaluation_to_depth(&mut self, depth: u32) -> Evaluation {
+        self.solver.evaluate(&mut self.board, depth)
     }
-}
 
-#[derive(Clone)]
-pub enum BoardKind {
-    XOBoard,
-    C4Board,
+    // Only finds out who wins with perfect play, not how quickly, which is much faster
+    // than evaluation(). Outcome::Unknown if the search is cancelled.
+    pub fn weak_solve(&mut self) -> Outcome {
+        self.solver.weak_solve(&mut self.board)
+    }
+
+    // Finds out whether the player can force a win with proof-number search, and if so
+    // how. Works for either player, not just the one to move. Gives up with Proof::Unknown
+    // after this many positions.
+    pub fn prove_win(&self, player: Player, max_nodes: usize) -> Proof {
+        proof::prove_win(&self.board, player, max_nodes)
+    }
+
+    // Saves everything the solver has worked out so far, to be loaded again later (even on
+    // another machine) with load_transpositions
+    pub fn save_transpositions(&self, writer: impl Write) -> io::Result<()> {
+        self.solver.save_transpositions(&self.board, writer)
+    }
+
+    // Adds saved results to the table. The file has to be for the same size board and
+    // kind of game, and heuristic scores in it are only right for the same evaluator.
+    pub fn load_transpositions(&mut self, reader: impl Read) -> io::Result<()> {
+        self.solver.load_transpositions(&self.board, reader)
+    }
+
+    // Solves every legal move, giving what evaluation() would say after playing it
+    // (counting the move itself). Moves are in the order generate_moves gives them.
+    pub fn analyse_moves(&mut self) -> Vec<(Move, Evaluation)> {
+        self.solver.analyse_moves(&self.board).into_iter()
+            .map(|(mov, score)| (mov, self.board.evaluation(score, self.board.moves_left())))
+            .collect()
+    }
 }
 
 #[derive(Clone)]
-pub struct Board {
+pub struct Board<R: GameRules = BoardKind, B: Bitboard = u64> {
     width: u32,
     height: u32,
     row: u32,
-    pub bitboards: [Bitboard; 2],
+    pub bitboards: [B; 2],
     player: Player,
-    top_mask: Bitboard,
+    top_mask: B,
     used_bits: u8,
-    col_tops: Vec<u64>,
-    kind: BoardKind,
+    symmetries: Arc<Vec<Vec<u32>>>,
+    // Zobrist hash of the position, then of the position under each symmetry
+    hashes: ArrayVec<u64, 8>,
+    rules: R,
 }
 
-impl Board {
-    fn can_play(&self, mov: Move) -> bool {
-        match self.kind {
-            BoardKind::XOBoard => !self.over() && !self.occupied(mov),
-            BoardKind::C4Board => self.col_tops[mov as usize] < self.height as u64,
-        }
+impl<R: GameRules, B: Bitboard> Board<R, B> {
+    pub fn can_play(&self, mov: Move) -> bool {
+        self.rules.can_play(self, mov)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // How many in a row wins
+    pub fn row(&self) -> u32 {
+        self.row
+    }
+
+    // Bits the board takes up, counting the spare bit at the top of each column
+    pub fn used_bits(&self) -> u8 {
+        self.used_bits
     }
 
-    fn get_index(&self, row: u32, column: u32) -> u32 {
+    pub fn rules(&self) -> &R {
+        &self.rules
+    }
+
+    pub fn format_move(&self, mov: Move) -> String {
+        self.rules.format_move(self, mov)
+    }
+
+    pub fn parse_move(&self, text: &str) -> Option<Move> {
+        self.rules.parse_move(self, text)
+    }
+
+    // The bit index of a cell, counting rows from the top. Each column has a spare bit
+    // at the top, so bits go up each column from the bottom then on to the next column.
+    pub fn get_index(&self, row: u32, column: u32) -> u32 {
         let height = self.height + 1;
         let row = row + 1;
         height - 1 - row + (column * height)
     }
 
     pub fn render(&self, debug: bool) -> String {
+        let mut lines = self.rules.render(self);
+
+        if debug {
+            lines.push('\n');
+            lines.push_str(&format!("X: {:b}\n", self.bitboards[Player::X as usize]));
+            lines.push_str(&format!("O: {:b}", self.bitboards[Player::O as usize]));
+        }
+
+        lines
+    }
+
+    // Each row of the board on its own line, with pieces as X and O and empty cells as .
+    pub fn grid(&self) -> String {
         let mut lines = String::new();
         for row in 0..self.height {
             let mut line = String::new();
             for col in 0..self.width {
-                let mask = 1 << self.get_index(row, col);
+                let mask = B::bit(self.get_index(row, col));
                 // line.push_str(&format!("{:02} ", self.get_index(row, col)))
 
-                if self.bitboards[Player::X as usize] & mask != 0 {
+                if !(self.bitboards[Player::X as usize] & mask).is_empty() {
                     line.push('X');
                     continue;
                 }
 
-                if self.bitboards[Player::O as usize] & mask != 0 {
+                if !(self.bitboards[Player::O as usize] & mask).is_empty() {
                     line.push('O');
                     continue;
                 }
@@ -208,13 +518,6 @@ impl Board {
             lines.push_str(&line);
             lines.push('\n');
         }
-
-        if debug {
-            lines.push('\n');
-            lines.push_str(&format!("X: {:b}\n", self.bitboards[Player::X as usize]));
-            lines.push_str(&format!("O: {:b}", self.bitboards[Player::O as usize]));
-        }
-
         lines
     }
 
@@ -222,24 +525,95 @@ impl Board {
         println!("{}", self.render(debug));
     }
 
-    fn occupied(&self, mov: Move) -> bool {
-        (self.bitboards[0] | self.bitboards[1]) & mov != 0
-        // (self.bitboards[Player::X as usize] & mask) >> idx != 0 || (self.bitboards[Player::O as usize] & mask) >> idx != 0
+    pub fn has_won(&self, player: Player) -> bool {
+        self.rules.has_won(self, player)
     }
 
-    pub fn has_won(&self, player: Player) -> bool {
-        // Modified version of https://github.com/qu1j0t3/fhourstones/blob/bf0e70ed9fe8128eeea8539f17dd41826f2cc6b6/Game.c#L108
+    // Whether the player has `row` pieces in a row in any direction
+    pub fn has_line(&self, player: Player) -> bool {
+        // Based on https://github.com/qu1j0t3/fhourstones/blob/bf0e70ed9fe8128eeea8539f17dd41826f2cc6b6/Game.c#L108
+        let bitboard = self.bitboards[player as usize];
+        [1, self.height + 1, self.height, self.height + 2].into_iter()
+            .any(|direction| !runs(bitboard, self.row, direction).is_empty())
+    }
+
+    // Every cell on the board, leaving out the spare bit at the top of each column
+    pub fn board_mask(&self) -> B {
+        B::low_bits(self.used_bits as u32) & !self.top_mask
+    }
+
+    // The bottom cell of every column
+    pub fn bottom_mask(&self) -> B {
+        ((self.top_mask << 1) | B::bit(0)) & self.board_mask()
+    }
+
+    // Empty cells the player to move could put a piece in right now
+    fn playable_cells(&self) -> B {
+        self.rules.playable_cells(self)
+    }
+
+    fn winning_cells(&self, player: Player) -> B {
+        self.rules.winning_cells(self, player)
+    }
+
+    // Empty cells that would complete a line for the player, whether or not they
+    // can be played in yet
+    pub fn line_cells(&self, player: Player) -> B {
         let bitboard = self.bitboards[player as usize];
-        let delta = self.row - 2;
-        let vert = bitboard & (bitboard >> 1);
-        if vert & (vert >> delta) != 0 { return true; }
-        let hori = bitboard & (bitboard >> (self.height + 1));
-        if hori & (hori >> delta * (self.height + 1)) != 0 { return true; }
-        let diag1 = bitboard & (bitboard >> self.height);
-        if diag1 & (diag1 >> delta * self.height) != 0 { return true; }
-        let diag2 = bitboard & (bitboard >> (self.height + 2));
-        if diag2 & (diag2 >> delta * (self.height + 2)) != 0 { return true; }
-        return false;
+        let shift = |offset: i32| match offset < 0 {
+            true => bitboard.checked_shl(offset.unsigned_abs()).unwrap_or(B::ZERO),
+            false => bitboard.checked_shr(offset as u32).unwrap_or(B::ZERO),
+        };
+
+        let mut cells = B::ZERO;
+        for direction in [1, self.height + 1, self.height, self.height + 2] {
+            // Try the empty cell in each position along the line
+            for gap in 0..self.row as i32 {
+                cells |= (0..self.row as i32)
+                    .filter(|i| *i != gap)
+                    .fold(!B::ZERO, |line, i| line & shift((i - gap) * direction as i32));
+            }
+        }
+        cells & !(self.bitboards[0] | self.bitboards[1]) & self.board_mask()
+    }
+
+    // The cells that a move the player to move can make would put a piece in, out of
+    // these ones. Rules don't have to keep playable_cells and winning_cells to cells
+    // their moves play in, so they're checked with this before they're trusted.
+    fn reachable(&self, cells: B) -> B {
+        if cells.is_empty() { return cells; }
+        self.generate_moves().fold(B::ZERO, |reached, mov| reached | (self.move_cell(mov) & cells))
+    }
+
+    // Cells the player to move can play in without the opponent winning straight after
+    fn non_losing_cells(&self) -> B {
+        let playable = self.playable_cells();
+        let threats = self.winning_cells(!self.player);
+        let blockable = self.reachable(threats & playable);
+        let forced = match blockable.count_ones() {
+            0 => playable,
+            1 => blockable,
+            _ => return B::ZERO, // Only one of them can be blocked
+        };
+
+        forced & !self.rules.dangerous_cells(self, threats)
+    }
+
+    // The cell a move puts a piece in
+    fn move_cell(&self, mov: Move) -> B {
+        self.rules.move_cell(self, mov)
+    }
+
+    // A move that doesn't need searching to find: a win, or else blocking a win. Only
+    // generated moves are looked at, so unreachable winning cells are never picked.
+    fn obvious_move(&self) -> Option<Move> {
+        let playable = self.playable_cells();
+        let wins = self.winning_cells(self.player) & playable;
+        let threats = self.winning_cells(!self.player) & playable;
+        let moves: Vec<Move> = self.generate_moves().collect();
+        moves.iter().find(|mov| !(self.move_cell(**mov) & wins).is_empty())
+            .or_else(|| moves.iter().find(|mov| !(self.move_cell(**mov) & threats).is_empty()))
+            .copied()
     }
 
     pub fn over(&self) -> bool {
@@ -248,157 +622,812 @@ impl Board {
             self.draw()
     }
 
+    pub fn moves_played(&self) -> u32 {
+        (self.bitboards[Player::X as usize] | self.bitboards[Player::O as usize]).count_ones()
+    }
+
+    pub fn moves_left(&self) -> u32 {
+        self.width * self.height - self.moves_played()
+    }
+
+    // Turns a score for the player to move, from a search this many plies deep, into an
+    // evaluation. Plies to win are counted from this position.
+    fn evaluation(&self, score: i32, depth: u32) -> Evaluation {
+        if score.abs() >= WIN_THRESHOLD {
+            let player = match score > 0 {
+                true => self.player,
+                false => !self.player,
+            };
+            // Wins score less the more pieces are on the board when they happen
+            let plies = WIN_SCORE - score.abs() - self.moves_played() as i32;
+            return Evaluation::Win { player, plies: plies as u32 };
+        }
+
+        match (depth >= self.moves_left(), self.player) {
+            // Nobody wins by the end of the game
+            (true, _) => Evaluation::Draw,
+            (false, Player::X) => Evaluation::Heuristic(score),
+            (false, Player::O) => Evaluation::Heuristic(-score),
+        }
+    }
+
+    // What game is being played on what size of board, as stored at the start of
+    // saved files so they aren't used for a different game
+    fn rules_header(&self) -> [u8; 4] {
+        [self.rules.id(), self.width as u8, self.height as u8, self.row as u8]
+    }
+
+    // The same position for every symmetry of the board is stored under one key, the
+    // smallest of the flipped and rotated positions' hashes. Also returns which symmetry
+    // that was (0 for none, otherwise one more than the index into symmetries).
+    fn canonical(&self) -> (u64, usize) {
+        let (symmetry, key) = self.hashes.iter().enumerate()
+            .min_by_key(|(_, hash)| **hash)
+            .expect("There is always the unflipped hash");
+        (*key, symmetry)
+    }
+
+    // Zobrist hash of the position, the same however it was reached
+    pub fn hash(&self) -> u64 {
+        self.hashes[0]
+    }
+
+    fn toggle_piece(&mut self, player: Player, index: u32) {
+        self.bitboards[player as usize] ^= B::bit(index);
+        self.hashes[0] ^= zobrist(player, index);
+        for (hash, permutation) in self.hashes[1..].iter_mut().zip(self.symmetries.iter()) {
+            *hash ^= zobrist(player, permutation[index as usize]);
+        }
+    }
+
+    // This board with these pieces on it instead, with whoever's turn it would be if
+    // the extra pieces had been played from here
+    fn with_pieces(&self, bitboards: [B; 2]) -> Board<R, B> {
+        let mut board = self.clone();
+        for player in [Player::X, Player::O] {
+            let mut changed = board.bitboards[player as usize] ^ bitboards[player as usize];
+            while !changed.is_empty() {
+                let index = changed.trailing_zeros();
+                board.toggle_piece(player, index);
+                changed ^= B::bit(index);
+            }
+        }
+        if (board.moves_played() + self.moves_played()) % 2 == 1 {
+            board.player = !board.player;
+        }
+        board
+    }
+
+    // A small number for each move, small enough to store in the transposition table
+    fn move_index(&self, mov: Move) -> u8 {
+        self.rules.move_index(self, mov)
+    }
+
+    fn index_move(&self, index: u8) -> Move {
+        self.rules.index_move(self, index)
+    }
+
+    // Moves the move the same way the symmetry moves the board
+    fn transform_move(&self, symmetry: usize, mov: Move) -> Move {
+        if symmetry == 0 { return mov; }
+        self.rules.transform_move(self, &self.symmetries[symmetry - 1], mov)
+    }
+
+    // Undoes transform_move
+    fn untransform_move(&self, symmetry: usize, mov: Move) -> Move {
+        if symmetry == 0 { return mov; }
+        let mut original = mov;
+        // Every symmetry undoes itself apart from the quarter turns, which take three goes
+        while self.transform_move(symmetry, original) != mov {
+            original = self.transform_move(symmetry, original);
+        }
+        original
+    }
+
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    // Every run of `row` cells, in any direction, that a player could win with
+    pub fn lines(&self) -> Vec<B> {
+        let mut lines = Vec::new();
+        let (width, height, row) = (self.width as i32, self.height as i32, self.row as i32);
+        for (dr, dc) in [(0, 1), (1, 0), (1, 1), (1, -1)] {
+            for r in 0..height {
+                for c in 0..width {
+                    let (end_r, end_c) = (r + dr * (row - 1), c + dc * (row - 1));
+                    if end_r < 0 || end_r >= height || end_c < 0 || end_c >= width {
+                        continue;
+                    }
+
+                    lines.push((0..row).fold(B::ZERO, |line, i| {
+                        line | B::bit(self.get_index((r + dr * i) as u32, (c + dc * i) as u32))
+                    }));
+                }
+            }
+        }
+        lines
+    }
+
     fn draw(&self) -> bool {
         (self.bitboards[Player::X as usize] | self.bitboards[Player::O as usize])
             .count_ones() == self.height * self.width && !self.has_won(Player::X) && !self.has_won(Player::O)
     }
 
-    pub fn placebit(&mut self, mov: Move) {
-        match self.kind {
-            BoardKind::XOBoard => {
-                self.bitboards[self.player as usize] |= mov;
-                self.player = !self.player;
-            }
-            BoardKind::C4Board => {
-                self.bitboards[self.player as usize] |= 1 << (self.col_tops[mov as usize] + mov * (self.height + 1) as u64);
-                self.col_tops[mov as usize] += 1;
-                self.player = !self.player;
-            }
+    // The score of a finished game for the player to move
+    fn terminal_score(&self) -> i32 {
+        let score = WIN_SCORE - self.moves_played() as i32;
+        if self.has_won(self.player) {
+            score
+        } else if self.has_won(!self.player) {
+            -score
+        } else {
+            0
         }
     }
 
+    pub fn try_play(&mut self, mov: Move) -> Result<(), MoveError> {
+        if self.over() { return Err(MoveError::GameOver); }
+        self.rules.check_move(self, mov)?;
+        self.placebit(mov);
+        Ok(())
+    }
+
+    pub fn placebit(&mut self, mov: Move) {
+        let cell = self.move_cell(mov);
+        self.toggle_piece(self.player, cell.trailing_zeros());
+        self.player = !self.player;
+    }
+
     pub fn undo_move(&mut self, mov: Move) {
-        match self.kind {
-            BoardKind::XOBoard => {
-                self.bitboards[(!self.player) as usize] ^= mov;
-                self.player = !self.player;
-            }
-            BoardKind::C4Board => {
-                self.col_tops[mov as usize] -= 1;
-                self.bitboards[!self.player as usize] ^= 1 << (self.col_tops[mov as usize] + mov * (self.height + 1) as u64);
-                self.player = !self.player;
-            }
+        let cell = self.rules.played_cell(self, mov);
+        self.toggle_piece(!self.player, cell.trailing_zeros());
+        self.player = !self.player;
+    }
+
+    pub fn generate_moves(&self) -> Moves<B> {
+        self.rules.generate_moves(self)
+    }
+}
+
+pub trait Evaluator<R: GameRules = BoardKind, B: Bitboard = u64>: Send + Sync {
+    // Guesses how good a position is for the player to move, used when the search
+    // stops before the end of the game. Positive is good, negative is bad.
+    fn evaluate(&self, board: &Board<R, B>) -> i32;
+}
+
+// Counts the lines each player could still complete, with lines that are
+// nearly complete counting for much more
+#[derive(Default)]
+pub struct OpenLines<B: Bitboard = u64> {
+    lines: OnceLock<Vec<B>>,
+}
+
+impl<B: Bitboard> OpenLines<B> {
+    fn line_score(&self, pieces: u32, row: u32) -> i32 {
+        match row - pieces {
+            1 => 9,
+            2 => 3,
+            _ => 1,
         }
     }
+}
 
-    pub fn generate_moves(&self) -> Moves {
-        match self.kind { // Probably not the best way of doing things
-            BoardKind::XOBoard => Moves::XOMoves((!(self.bitboards[0] | self.bitboards[1])) & !((!0 << (self.width * (self.height + 1))) | self.top_mask), 0, self.used_bits),
-            BoardKind::C4Board => {
-                Moves::C4Moves(self.col_tops.iter().enumerate().filter_map(|x| {
-                    if *x.1 < self.height as u64 {
-                        return Some(x.0 as Move);
-                    }
-                    None
-                }).collect(), 0)
+impl<R: GameRules, B: Bitboard> Evaluator<R, B> for OpenLines<B> {
+    fn evaluate(&self, board: &Board<R, B>) -> i32 {
+        let lines = self.lines.get_or_init(|| board.lines());
+        let ours = board.bitboards[board.player as usize];
+        let theirs = board.bitboards[!board.player as usize];
+
+        lines.iter().map(|line| {
+            match ((ours & *line).count_ones(), (theirs & *line).count_ones()) {
+                (0, 0) => 0,
+                (pieces, 0) => self.line_score(pieces, board.row),
+                (0, pieces) => -self.line_score(pieces, board.row),
+                _ => 0,
             }
-        }
+        }).sum()
     }
 }
 
-enum ScoreKind {
-    Exact,
-    LowerBound,
-    UpperBound,
+// Lets another thread stop a search that's in progress, e.g. when the user moves
+// or closes the game. The search then returns the best move it found so far.
+// Cancelling only affects a search that has already started.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+// Who wins with perfect play, for the player to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+    // The search was cancelled before it found out
+    Unknown,
+}
+
+// How the search finds the score of a position
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    // Plain alpha-beta with a full window
+    #[default]
+    AlphaBeta,
+    // Principal variation search: full window for the first move, null windows for the rest
+    Pvs,
+    // MTD(f): a series of null window searches, homing in on the score
+    Mtdf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Move,
+    // Deepest search depth (in plies) that finished before the budget ran out
+    pub depth: u32,
+    // The move is known to be optimal rather than the best found so far
+    pub proven: bool,
+    // What the deepest finished search thought of the position
+    pub evaluation: Evaluation,
+    pub stats: SearchStats,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub nodes: u64,
+    // Transposition table lookups, and how many of them found a usable score
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    // Times the rest of a position's moves were skipped by alpha-beta pruning
+    pub cutoffs: u64,
+    // Depth (in plies) of the search in progress, or of the last one
+    pub depth: u32,
+    pub elapsed: Duration,
 }
 
-struct Score {
-    value: i32,
-    kind: ScoreKind,
+impl SearchStats {
+    pub fn nodes_per_second(&self) -> u64 {
+        match self.elapsed.as_micros() {
+            0 => 0,
+            micros => (self.nodes as u128 * 1_000_000 / micros) as u64,
+        }
+    }
+
+    pub fn tt_hit_rate(&self) -> f64 {
+        match self.tt_probes {
+            0 => 0.0,
+            probes => self.tt_hits as f64 / probes as f64,
+        }
+    }
 }
 
-pub struct Solver {
-    transpositions: Arc<FxDashMap<([u64; 2], i32), Score>>
+// Called every so often during a search with the statistics so far and the best
+// move found so far, if there is one yet
+pub type ProgressCallback = Box<dyn Fn(&SearchStats, Option<Move>) + Send + Sync>;
+
+#[derive(Default)]
+struct Counters {
+    nodes: AtomicU64,
+    tt_probes: AtomicU64,
+    tt_hits: AtomicU64,
+    cutoffs: AtomicU64,
+    depth: AtomicU32,
+    // Milliseconds into the search that progress was last reported
+    last_report: AtomicU64,
+}
+
+pub struct Solver<R: GameRules = BoardKind, B: Bitboard = u64> {
+    transpositions: TranspositionTable,
+    evaluator: Box<dyn Evaluator<R, B>>,
+    max_depth: Option<u32>,
+    move_ordering: MoveOrdering,
+    search_mode: SearchMode,
+    ordering_tables: OrderingTables,
+    deadline: Option<Instant>,
+    cancel: CancelToken,
+    progress: Option<ProgressCallback>,
+    started: Instant,
+    counters: Counters,
+    // Best move found so far at the root of the search in progress
+    best_so_far: Mutex<Option<(Move, i32)>>,
+    last_stats: SearchStats,
+    book: Option<OpeningBook>,
+    tablebase: Option<Tablebase>,
+    difficulty: Difficulty,
+    rng: SmallRng,
 }
 
-impl Solver {
-    fn new() -> Solver {
-        Solver { transpositions: Arc::new(FxDashMap::default()) }
+impl<R: GameRules, B: Bitboard> Solver<R, B> {
+    fn new() -> Solver<R, B> {
+        Solver {
+            transpositions: TranspositionTable::new(DEFAULT_TABLE_SIZE_MB),
+            evaluator: Box::new(OpenLines::default()),
+            max_depth: None,
+            move_ordering: MoveOrdering::default(),
+            search_mode: SearchMode::default(),
+            ordering_tables: OrderingTables::default(),
+            deadline: None,
+            cancel: CancelToken::default(),
+            progress: None,
+            started: Instant::now(),
+            counters: Counters::default(),
+            best_so_far: Mutex::new(None),
+            last_stats: SearchStats::default(),
+            book: None,
+            tablebase: None,
+            difficulty: Difficulty::default(),
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator<R, B>>) {
+        self.evaluator = evaluator;
+        self.clear_transpositions();
+    }
+
+    // Stops searching this many plies ahead and guesses with the evaluator instead.
+    // None searches to the end of the game.
+    pub fn set_max_depth(&mut self, max_depth: Option<u32>) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn max_depth(&self) -> Option<u32> {
+        self.max_depth
+    }
+
+    // Also sets the max depth. Only best_move makes mistakes on purpose.
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty;
+        self.max_depth = difficulty.max_depth;
+    }
+
+    // Moves chosen at random (by the difficulty) are the same every time for the same seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    pub fn set_move_ordering(&mut self, move_ordering: MoveOrdering) {
+        self.move_ordering = move_ordering;
+    }
+
+    pub fn set_search_mode(&mut self, search_mode: SearchMode) {
+        self.search_mode = search_mode;
+    }
+
+    // Positions in the book are looked up instead of searched. A book for a
+    // different size of board or kind of game is ignored.
+    pub fn set_opening_book(&mut self, book: Option<OpeningBook>) {
+        self.book = book;
+    }
+
+    // Like an opening book, but for every position, so there's nothing left to search
+    pub fn set_tablebase(&mut self, tablebase: Option<Tablebase>) {
+        self.tablebase = tablebase;
+    }
+
+    fn search_depth(&self, board: &Board<R, B>) -> u32 {
+        match self.max_depth {
+            Some(depth) => min(depth, board.moves_left()),
+            None => board.moves_left(),
+        }
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
     }
 
-    fn best_move(&mut self, board: &mut Board) -> Move {
-        let player: i8 = match board.player {
-            Player::O => -1,
-            Player::X => 1,
+    pub fn set_progress_callback(&mut self, progress: Option<ProgressCallback>) {
+        self.progress = progress;
+    }
+
+    // Statistics from the last search
+    pub fn stats(&self) -> SearchStats {
+        self.last_stats
+    }
+
+    fn current_stats(&self) -> SearchStats {
+        SearchStats {
+            nodes: self.counters.nodes.load(Ordering::Relaxed),
+            tt_probes: self.counters.tt_probes.load(Ordering::Relaxed),
+            tt_hits: self.counters.tt_hits.load(Ordering::Relaxed),
+            cutoffs: self.counters.cutoffs.load(Ordering::Relaxed),
+            depth: self.counters.depth.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    fn start_search(&mut self, deadline: Option<Instant>) {
+        self.transpositions.new_search();
+        self.deadline = deadline;
+        self.cancel.reset();
+        self.started = Instant::now();
+        self.counters = Counters::default();
+        self.ordering_tables = OrderingTables::default();
+    }
+
+    fn finish_search(&mut self) {
+        self.deadline = None;
+        self.last_stats = self.current_stats();
+    }
+
+    fn best_move(&mut self, board: &mut Board<R, B>) -> Move {
+        self.start_search(None);
+        let depth = self.search_depth(board) as i32;
+        let chosen = match self.difficulty.plays_best() {
+            true => self.search_root(board, depth),
+            false => {
+                let scores = self.score_moves(board, depth);
+                match self.cancel.is_cancelled() {
+                    true => None,
+                    false => self.difficulty.choose(&scores, &mut self.rng),
+                }
+            },
         };
-        let moves: Vec<Move> = board.generate_moves().collect();
-        let best_move = moves.par_iter().max_by_key(|x| { let mut cloned_board = board.clone(); cloned_board.placebit(**x); -self.negamax(&mut cloned_board, WIN_SCORE, NEGINFINITY, INFINITY, player) });
+        // Cancelled, so make do with the moves that were searched
+        let best_move = chosen.or(*self.best_so_far.lock().unwrap());
+        self.finish_search();
+        match best_move {
+            Some((best_move, _)) => best_move,
+            // Cancelled before any move was searched, so any move will have to do
+            None => board.generate_moves().next().expect("No move was chosen"),
+        }
+    }
+
+    // Solves the position to the end of the game
+    fn solve(&mut self, board: &mut Board<R, B>) -> Evaluation {
+        let score = self.solve_score(board);
+        match self.cancel.is_cancelled() {
+            true => Evaluation::Unknown,
+            false => board.evaluation(score, board.moves_left()),
+        }
+    }
+
+    // Searches this many plies ahead, or to the end of the game if that's sooner
+    fn evaluate(&mut self, board: &mut Board<R, B>, depth: u32) -> Evaluation {
+        self.start_search(None);
+        let depth = min(depth, board.moves_left());
+        self.counters.depth.store(depth, Ordering::Relaxed);
+        let score = self.search(board, depth as i32);
+        self.finish_search();
+        match self.cancel.is_cancelled() {
+            true => Evaluation::Unknown,
+            false => board.evaluation(score, depth),
+        }
+    }
+
+    pub(crate) fn solve_score(&mut self, board: &mut Board<R, B>) -> i32 {
+        self.start_search(None);
+        let depth = board.moves_left();
+        self.counters.depth.store(depth, Ordering::Relaxed);
+        let score = self.search(board, depth as i32);
+        self.finish_search();
+        score
+    }
+
+    // Solves the position with a window that only tells wins, draws and losses apart,
+    // since every won score is above 1 and every lost one below -1
+    fn weak_solve(&mut self, board: &mut Board<R, B>) -> Outcome {
+        self.start_search(None);
+        let depth = board.moves_left() as i32;
+        self.counters.depth.store(depth as u32, Ordering::Relaxed);
+        let score = self.negamax(board, depth, -1, 1);
+        self.finish_search();
+        match score {
+            _ if self.cancel.is_cancelled() => Outcome::Unknown,
+            0 => Outcome::Draw,
+            x if x > 0 => Outcome::Win,
+            _ => Outcome::Loss,
+        }
+    }
+
+    fn analyse_moves(&mut self, board: &Board<R, B>) -> Vec<(Move, i32)> {
+        self.start_search(None);
+        let scores = self.score_moves(board, board.moves_left() as i32);
+        self.finish_search();
+        scores
+    }
+
+    // Iterative deepening: searches one ply deeper at a time until the budget runs out,
+    // and returns the best move from the deepest search that finished
+    fn best_move_within(&mut self, board: &Board<R, B>, budget: Duration) -> SearchResult {
+        self.start_search(Some(Instant::now() + budget));
+
+        let max_depth = self.search_depth(board);
+        let mut result = SearchResult {
+            best_move: board.generate_moves().next().expect("No move was chosen"),
+            depth: 0,
+            proven: false,
+            evaluation: Evaluation::Unknown,
+            stats: SearchStats::default(),
+        };
+
+        for depth in 1..=max_depth {
+            let (best_move, score) = match self.search_root(board, depth as i32) {
+                Some(found) => found,
+                None => break,
+            };
+
+            // Searching to the end of the game, or finding a forced result, can't be improved on
+            let proven = depth == board.moves_left() || score.abs() >= WIN_THRESHOLD;
+            result = SearchResult {
+                best_move,
+                depth,
+                proven,
+                evaluation: board.evaluation(score, depth),
+                stats: self.current_stats(),
+            };
+            if let Some(progress) = &self.progress {
+                progress(&result.stats, Some(best_move));
+            }
+            if proven { break; }
+        }
+
+        self.finish_search();
+        result
+    }
+
+    // Returns None if the search was stopped before it finished
+    fn search_root(&self, board: &Board<R, B>, depth: i32) -> Option<(Move, i32)> {
+        let best_move = self.score_moves(board, depth).into_iter().max_by_key(|x| x.1);
         // println!("Evaluation: {}", match best_score.cmp(&0) {
         //     Ordering::Equal => "draw".to_string(),
         //     Ordering::Greater => format!("X wins in {} moves", (best_score - INFINITY) * -1),
         //     Ordering::Less => format!("O wins in {} moves", best_score * -1),
         // });
-        *best_move.expect("No move was chosen")
+        if self.cancel.is_cancelled() { return None; }
+
+        // Remembered so that the principal variation can start from here
+        if let Some((mov, value)) = best_move {
+            let (key, symmetry) = board.canonical();
+            self.transpositions.insert(key, Score {
+                value,
+                depth,
+                kind: ScoreKind::Exact,
+                best_move: Some(board.move_index(board.transform_move(symmetry, mov))),
+            });
+        }
+        best_move
     }
 
-    fn negamax(&self, board: &mut Board, depth: i32, mut alpha: i32, mut beta: i32, player: i8) -> i32 {
-        let orig_alpha = alpha;
-        if /*depth == 0 ||*/ board.over() {
-            if board.draw() {
-                return 0;
+    // Follows the best moves stored in the transposition table from this position,
+    // giving the line of play the last search expects
+    fn principal_variation(&self, board: &Board<R, B>) -> Vec<Move> {
+        let mut board = board.clone();
+        let mut moves = Vec::new();
+        while !board.over() {
+            let (key, symmetry) = board.canonical();
+            let mov = match self.transpositions.get(key).and_then(|position| position.best_move) {
+                Some(index) => board.untransform_move(symmetry, board.index_move(index)),
+                // The search doesn't store positions it finds without searching
+                None => match board.obvious_move() {
+                    Some(mov) => mov,
+                    None => break,
+                },
+            };
+            if !board.can_play(mov) { break; }
+            board.placebit(mov);
+            moves.push(mov);
+        }
+        moves
+    }
+
+    // Scores every move, leaving out any whose search was stopped part way through
+    fn score_moves(&self, board: &Board<R, B>, depth: i32) -> Vec<(Move, i32)> {
+        self.counters.depth.store(depth as u32, Ordering::Relaxed);
+        *self.best_so_far.lock().unwrap() = None;
+
+        let moves: Vec<Move> = board.generate_moves().collect();
+        moves.par_iter().filter_map(|x| {
+            let mut cloned_board = board.clone();
+            cloned_board.placebit(*x);
+            let score = -self.search(&mut cloned_board, depth - 1);
+            if self.cancel.is_cancelled() {
+                return None;
             }
 
-            if board.has_won(Player::X) {
-                // return (INFINITY - 1) * (player as i32)
-                return -(depth) * (player as i32);
+            let mut best_so_far = self.best_so_far.lock().unwrap();
+            if best_so_far.is_none_or(|(_, best_score)| score > best_score) {
+                *best_so_far = Some((*x, score));
             }
+            Some((*x, score))
+        }).collect()
+    }
 
-            if board.has_won(Player::O) {
-                // return (NEGINFINITY + 1) * (player as i32);
-                return (depth) * (player as i32);
+    // Finds the exact score of the position, in whichever way the search mode says
+    fn search(&self, board: &mut Board<R, B>, depth: i32) -> i32 {
+        match self.search_mode {
+            SearchMode::Mtdf => self.mtdf(board, depth),
+            SearchMode::AlphaBeta | SearchMode::Pvs => self.negamax(board, depth, NEGINFINITY, INFINITY),
+        }
+    }
+
+    // Narrows down the score with null window searches, which only say whether the score
+    // is above or below a value but prune much more than a full window search
+    fn mtdf(&self, board: &mut Board<R, B>, depth: i32) -> i32 {
+        let moves_played = board.moves_played() as i32;
+        // From having already lost, to winning with the next move
+        let mut lower = -(WIN_SCORE - moves_played);
+        let mut upper = WIN_SCORE - (moves_played + 1);
+
+        while lower < upper && !self.cancel.is_cancelled() {
+            // Finding out who wins first splits the possible scores best, since solved
+            // positions can only score draws, wins or losses
+            let beta = [1, 0, WIN_THRESHOLD, -WIN_THRESHOLD + 1].into_iter()
+                .find(|beta| lower < *beta && *beta <= upper)
+                .unwrap_or_else(|| (lower as i64 + (upper as i64 - lower as i64 + 1) / 2) as i32);
+
+            let score = self.negamax(board, depth, beta - 1, beta);
+            match score >= beta {
+                true => lower = score,
+                false => upper = score,
             }
+        }
+        lower
+    }
 
-            panic!();
+    // Counts the node, and every so often checks the clock to see whether
+    // the search is out of time or progress should be reported
+    fn visit_node(&self) {
+        let nodes = self.counters.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if !nodes.is_multiple_of(CLOCK_POLL_NODES) {
+            return;
         }
 
+        let now = Instant::now();
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            self.cancel.cancel();
+        }
 
-        if self.transpositions.contains_key(&(board.bitboards, depth)) {
-            let position = self.transpositions.get(&(board.bitboards, depth)).unwrap();
-            match position.kind {
-                ScoreKind::Exact => return position.value,
-                ScoreKind::LowerBound => alpha = max(alpha, position.value),
-                ScoreKind::UpperBound => beta = min(beta, position.value),
+        if let Some(progress) = &self.progress {
+            let elapsed = (now - self.started).as_millis() as u64;
+            let last_report = self.counters.last_report.load(Ordering::Relaxed);
+            if elapsed >= last_report + PROGRESS_INTERVAL.as_millis() as u64 &&
+                self.counters.last_report.compare_exchange(last_report, elapsed, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                let best_move = self.best_so_far.lock().unwrap().map(|(mov, _)| mov);
+                progress(&self.current_stats(), best_move);
             }
+        }
+    }
 
-            if alpha >= beta {
-                return position.value
+    // Scores are from the point of view of the player to move. Wins score higher
+    // the fewer pieces are on the board, so that quicker wins are preferred.
+    fn negamax(&self, board: &mut Board<R, B>, depth: i32, mut alpha: i32, mut beta: i32) -> i32 {
+        let orig_alpha = alpha;
+        self.visit_node();
+        if board.over() {
+            return board.terminal_score();
+        }
+
+        if self.cancel.is_cancelled() {
+            return 0;
+        }
+
+        if let Some(score) = self.book.as_ref()
+            .filter(|book| book.matches(board))
+            .and_then(|book| book.get(board))
+            .or_else(|| self.tablebase.as_ref()
+                .filter(|tablebase| tablebase.matches(board))
+                .and_then(|tablebase| tablebase.get(board))) {
+            return score;
+        }
+
+        if depth == 0 {
+            return self.evaluator.evaluate(board).clamp(-WIN_THRESHOLD + 1, WIN_THRESHOLD - 1);
+        }
+
+        // Nothing is gained by searching deeper than the end of the game
+        let depth = min(depth, board.moves_left() as i32);
+
+        // Win straight away if possible, and otherwise don't bother with
+        // moves that let the opponent win straight away
+        let moves_played = board.moves_played() as i32;
+        if !board.reachable(board.winning_cells(board.player) & board.playable_cells()).is_empty() {
+            return WIN_SCORE - (moves_played + 1);
+        }
+        let non_losing = board.non_losing_cells();
+        if non_losing.is_empty() {
+            return -(WIN_SCORE - (moves_played + 2));
+        }
+
+        self.counters.tt_probes.fetch_add(1, Ordering::Relaxed);
+        let (key, symmetry) = board.canonical();
+        let mut tt_move = None;
+        if let Some(position) = self.transpositions.get(key) {
+            tt_move = position.best_move.map(|index| board.untransform_move(symmetry, board.index_move(index)));
+            if position.depth >= depth {
+                self.counters.tt_hits.fetch_add(1, Ordering::Relaxed);
+                match position.kind {
+                    ScoreKind::Exact => return position.value,
+                    ScoreKind::LowerBound => alpha = max(alpha, position.value),
+                    ScoreKind::UpperBound => beta = min(beta, position.value),
+                }
+
+                if alpha >= beta {
+                    return position.value
+                }
             }
         }
 
-        // let moves: Vec<Move> = order_moves(board.clone(), generate_moves(&board), player);
-        let moves = board.generate_moves();
-        // moves.sort_by_cached_key(|a| score_move(&mut board, a));
-        // moves.reverse();
-        // moves.sort_by(|a, b| score_move(&mut board, b).cmp(&score_move(&mut board, a)));
+        let mut moves = self.ordering_tables.order(&self.move_ordering, board, tt_move);
+        moves.retain(|mov| !(board.move_cell(*mov) & non_losing).is_empty());
 
         let mut value = NEGINFINITY;
-        for mov in moves {
+        let mut best_move = None;
+        for (i, mov) in moves.into_iter().enumerate() {
             board.placebit(mov);
-            let ngresult = -self.negamax(board, depth - 1, -beta, -alpha, -player);
+            let ngresult = match self.search_mode {
+                // Check whether the rest of the moves are worse than the first with a null
+                // window, and only search them properly if they aren't
+                SearchMode::Pvs if i > 0 => {
+                    let score = -self.negamax(board, depth - 1, -alpha - 1, -alpha);
+                    match alpha < score && score < beta {
+                        true => -self.negamax(board, depth - 1, -beta, -alpha),
+                        false => score,
+                    }
+                },
+                _ => -self.negamax(board, depth - 1, -beta, -alpha),
+            };
             board.undo_move(mov);
             // value = max(value, -negamax(board2, depth - 1, -beta, -alpha, -player)); // Beta and Alpha are swapped here
-            value = max(value, ngresult);
+            if ngresult > value {
+                value = ngresult;
+                best_move = Some(mov);
+            }
             alpha = max(value, alpha);
             if alpha >= beta {
                 // print!("snip!");
+                self.counters.cutoffs.fetch_add(1, Ordering::Relaxed);
+                self.ordering_tables.record_cutoff(&self.move_ordering, board, mov, depth);
                 break;
             }
         }
 
-        self.transpositions.insert((board.bitboards, depth), Score {
+        // A stopped search returns garbage, so don't remember it
+        if self.cancel.is_cancelled() {
+            return 0;
+        }
+
+        self.transpositions.insert(key, Score {
             value,
+            depth,
             kind: match value {
                 x if x <= orig_alpha => ScoreKind::UpperBound,
                 x if x >= beta => ScoreKind::LowerBound,
                 _ => ScoreKind::Exact,
-            }
+            },
+            best_move: best_move.map(|mov| board.move_index(board.transform_move(symmetry, mov))),
         });
 
         // alpha
         value
     }
 
+    // Replaces the transposition table with an empty one of about this many megabytes
+    pub fn set_table_size(&mut self, size_mb: usize) {
+        self.transpositions = TranspositionTable::new(size_mb);
+    }
+
     pub fn clear_transpositions(&mut self) {
         self.transpositions.clear();
     }
+
+    fn save_transpositions(&self, board: &Board<R, B>, writer: impl Write) -> io::Result<()> {
+        self.transpositions.write_to(board.rules_header(), writer)
+    }
+
+    fn load_transpositions(&mut self, board: &Board<R, B>, reader: impl Read) -> io::Result<()> {
+        self.transpositions.read_from(board.rules_header(), reader)
+    }
 }
@@ -0,0 +1,102 @@
+use rayon::prelude::*;
+use crate::{book::{decode, encode, ScoreTable}, Bitboard, Board, Evaluation, Game, GameRules};
+
+// The solved score of every position reachable from a starting position, found by
+// retrograde analysis rather than search: every position is listed a ply at a time,
+// then scored from the last ply back to the first, each from the scores of the
+// positions its moves lead to. Only feasible for small boards.
+pub struct Tablebase {
+    table: ScoreTable,
+}
+
+// Only the pieces are kept for each position until it's scored, since there can be
+// millions of them and a whole board is many times bigger
+type Pieces<B> = [B; 2];
+
+// Every unfinished position one move on from the positions, one for each set of
+// symmetrical positions, sorted by canonical key. Boards are rebuilt from the start
+// board to play the moves on.
+fn next_ply<R: GameRules, B: Bitboard>(start: &Board<R, B>, positions: &[(u64, Pieces<B>)]) -> Vec<(u64, Pieces<B>)> {
+    let mut next: Vec<(u64, Pieces<B>)> = positions.par_iter()
+        .flat_map_iter(|(_, pieces)| {
+            let board = start.with_pieces(*pieces);
+            board.generate_moves().map(move |mov| {
+                let mut next = board.clone();
+                next.placebit(mov);
+                next
+            })
+        })
+        .filter(|board| !board.over())
+        .map(|board| (board.canonical().0, board.bitboards))
+        .collect();
+    next.par_sort_unstable_by_key(|(key, _)| *key);
+    next.dedup_by_key(|(key, _)| *key);
+    next
+}
+
+impl Tablebase {
+    pub fn generate<R: GameRules, B: Bitboard>(game: &Game<R, B>) -> Tablebase {
+        let start = &game.board;
+        let mut plies = Vec::new();
+        let mut positions = match start.over() {
+            true => Vec::new(),
+            false => vec![(start.canonical().0, start.bitboards)],
+        };
+        while !positions.is_empty() {
+            let next = next_ply(start, &positions);
+            plies.push(positions);
+            positions = next;
+        }
+
+        let mut entries = Vec::new();
+        // Scores of the positions at the ply after the one being scored
+        let mut next: Vec<(u64, i8)> = Vec::new();
+        while let Some(positions) = plies.pop() {
+            let scores: Vec<(u64, i8)> = positions.par_iter().map(|(key, pieces)| {
+                let board = start.with_pieces(*pieces);
+                let score = board.generate_moves().map(|mov| {
+                    let mut child = board.clone();
+                    child.placebit(mov);
+                    if child.over() { return -child.terminal_score(); }
+
+                    let key = child.canonical().0;
+                    let index = next.binary_search_by_key(&key, |(key, _)| *key)
+                        .expect("Position missing from the next ply");
+                    -decode(next[index].1)
+                }).max().expect("Unfinished position with no moves");
+                (*key, encode(score))
+            }).collect();
+
+            entries.extend_from_slice(&next);
+            next = scores;
+        }
+        entries.extend(next);
+
+        Tablebase { table: ScoreTable::new(start.rules_header(), entries) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Whether the tablebase was made for the same game as the board
+    pub fn matches<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> bool {
+        self.table.matches(board)
+    }
+
+    // The solved score of the position for the player to move, if it's in the tablebase
+    pub(crate) fn get<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Option<i32> {
+        self.table.get(board)
+    }
+
+    // What perfect play leads to from the position, or None if it isn't in the tablebase
+    pub fn evaluation<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Option<Evaluation> {
+        if !self.matches(board) { return None; }
+        if board.over() { return Some(board.evaluation(board.terminal_score(), 0)); }
+        self.get(board).map(|score| board.evaluation(score, board.moves_left()))
+    }
+}
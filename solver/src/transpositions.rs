@@ -0,0 +1,191 @@
+use std::{io::{self, Read, Write}, sync::atomic::{AtomicU64, Ordering}};
+use crate::invalid_data;
+
+// Each bucket holds one entry that is only replaced by a deeper search (or once it's
+// from an old search), and one that is always replaced, so a deep result isn't lost to
+// lots of shallow ones but recent shallow results are still kept
+const ENTRIES_PER_BUCKET: usize = 2;
+
+// Saved tables are a header (magic, version, rules (see Board::rules) and the number of
+// entries) followed by each entry's key and packed data as little endian u64s
+const MAGIC: &[u8; 4] = b"XOTT";
+const VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreKind {
+    Exact = 1,
+    LowerBound = 2,
+    UpperBound = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Score {
+    pub value: i32,
+    pub depth: i32,
+    pub kind: ScoreKind,
+    // Stored as a move index (see Board::move_index) so it fits in the entry
+    pub best_move: Option<u8>,
+}
+
+// Packed into one word as value (32 bits), depth (8 bits), kind (2 bits),
+// best move + 1 (8 bits, 0 for none) and generation (8 bits)
+fn pack(score: &Score, generation: u8) -> u64 {
+    (score.value as u32 as u64) |
+        (score.depth.clamp(0, u8::MAX as i32) as u64) << 32 |
+        (score.kind as u64) << 40 |
+        (score.best_move.map_or(0, |mov| mov as u64 + 1)) << 42 |
+        (generation as u64) << 50
+}
+
+fn unpack(data: u64) -> Option<Score> {
+    let kind = match (data >> 40) & 0b11 {
+        1 => ScoreKind::Exact,
+        2 => ScoreKind::LowerBound,
+        3 => ScoreKind::UpperBound,
+        _ => return None, // Empty entry
+    };
+
+    Some(Score {
+        value: data as u32 as i32,
+        depth: ((data >> 32) & 0xff) as i32,
+        kind,
+        best_move: match (data >> 42) & 0xff {
+            0 => None,
+            mov => Some(mov as u8 - 1),
+        },
+    })
+}
+
+fn generation(data: u64) -> u8 {
+    (data >> 50) as u8
+}
+
+// The key is stored xored with the data, so that an entry torn by two threads
+// writing at once no longer matches its key and is ignored, without needing locks
+#[derive(Default)]
+struct Entry {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Entry {
+    fn load(&self, key: u64) -> Option<u64> {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.check.load(Ordering::Relaxed) ^ data == key).then_some(data)
+    }
+
+    fn store(&self, key: u64, data: u64) {
+        self.data.store(data, Ordering::Relaxed);
+        self.check.store(key ^ data, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.store(0, 0);
+    }
+}
+
+#[derive(Default)]
+struct Bucket {
+    entries: [Entry; ENTRIES_PER_BUCKET],
+}
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    // Bumped every search, so that entries from old searches can be replaced
+    generation: u8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let buckets = (size_mb << 20) / std::mem::size_of::<Bucket>();
+        TranspositionTable {
+            buckets: (0..buckets.max(1)).map(|_| Bucket::default()).collect(),
+            generation: 0,
+        }
+    }
+
+    // Keys are already hashes, so they only need scaling down to the table size
+    fn bucket(&self, key: u64) -> &Bucket {
+        &self.buckets[((key as u128 * self.buckets.len() as u128) >> 64) as usize]
+    }
+
+    pub fn get(&self, key: u64) -> Option<Score> {
+        self.bucket(key).entries.iter()
+            .find_map(|entry| entry.load(key))
+            .and_then(unpack)
+    }
+
+    pub fn insert(&self, key: u64, score: Score) {
+        let [deepest, recent] = &self.bucket(key).entries;
+        let data = pack(&score, self.generation);
+
+        let keep_deepest = match deepest.load(key) {
+            // Only a search at least as deep replaces the position that's already here
+            Some(old) => unpack(old).is_some_and(|old_score| old_score.depth > score.depth),
+            None => {
+                let old = deepest.data.load(Ordering::Relaxed);
+                unpack(old).is_some_and(|old_score| {
+                    generation(old) == self.generation && old_score.depth > score.depth
+                })
+            },
+        };
+
+        match keep_deepest {
+            true => recent.store(key, data),
+            false => deepest.store(key, data),
+        }
+    }
+
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    // Only the entries are saved, so a table can be loaded into one of a different size
+    pub fn write_to(&self, rules: [u8; 4], mut writer: impl Write) -> io::Result<()> {
+        let entries: Vec<(u64, u64)> = self.buckets.iter()
+            .flat_map(|bucket| &bucket.entries)
+            .map(|entry| {
+                let data = entry.data.load(Ordering::Relaxed);
+                (entry.check.load(Ordering::Relaxed) ^ data, data)
+            })
+            .filter(|(_, data)| unpack(*data).is_some())
+            .collect();
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&rules)?;
+        writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+        for (key, data) in entries {
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&data.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_from(&self, rules: [u8; 4], mut reader: impl Read) -> io::Result<()> {
+        let mut header = [0; 17];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC { return Err(invalid_data("not a saved transposition table")); }
+        if header[4] != VERSION { return Err(invalid_data("unsupported transposition table version")); }
+        if header[5..9] != rules { return Err(invalid_data("transposition table is for a different game")); }
+
+        let count = u64::from_le_bytes(header[9..17].try_into().unwrap());
+        let mut entry = [0; 16];
+        for _ in 0..count {
+            reader.read_exact(&mut entry)?;
+            let key = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let data = u64::from_le_bytes(entry[8..].try_into().unwrap());
+            let score = unpack(data).ok_or_else(|| invalid_data("empty transposition table entry"))?;
+            self.insert(key, score);
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        for bucket in &self.buckets {
+            for entry in &bucket.entries {
+                entry.clear();
+            }
+        }
+    }
+}
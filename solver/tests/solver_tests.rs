@@ -1,10 +1,11 @@
 use arrayvec::ArrayVec;
-use solver::{Moves, Game, Move};
+use solver::{Bitboard, Bitboard256, Board, BoardKind, ConfigError, GameConfig, Difficulty, Engine, Evaluation, GameRules, Moves, Game, Move, MoveError, MoveOrdering, OpeningBook, Outcome, Player, Proof, SearchMode, Tablebase};
+use std::{collections::HashSet, sync::{Arc, atomic::{AtomicU32, Ordering}}, thread, time::{Duration, Instant}};
 
 macro_rules! arrayvec {
     ( $( $x:expr ),* ) => {
         {
-            let mut temp_vec = ArrayVec::<Move, 10>::new();
+            let mut temp_vec = ArrayVec::<Move, 16>::new();
             $(
                 temp_vec.push($x);
             )*
@@ -206,19 +207,19 @@ fn test_connect_4_move_generation() {
 
 #[test]
 fn test_xo_moves() {
-    let mut moves = Moves::XOMoves(0b11101110111, 0, 11);
-    assert_eq!(moves.next().unwrap(), 0b1);
-    assert_eq!(moves.next().unwrap(), 0b10);
-    assert_eq!(moves.next().unwrap(), 0b100);
+    let mut moves = Moves::XOMoves(0b11101110111u64, 0, 11);
+    assert_eq!(moves.next().unwrap(), 0);
+    assert_eq!(moves.next().unwrap(), 1);
+    assert_eq!(moves.next().unwrap(), 2);
 
-    assert_eq!(moves.next().unwrap(), 0b10000);
-    assert_eq!(moves.next().unwrap(), 0b100000);
-    assert_eq!(moves.next().unwrap(), 0b1000000);
+    assert_eq!(moves.next().unwrap(), 4);
+    assert_eq!(moves.next().unwrap(), 5);
+    assert_eq!(moves.next().unwrap(), 6);
 }
 
 #[test]
 fn test_c4_moves() {
-    let mut moves = Moves::C4Moves(arrayvec![1,2,3,4], 0);
+    let mut moves: Moves = Moves::C4Moves(arrayvec![1,2,3,4], 0);
     assert_eq!(moves.next().unwrap(), 1);
     assert_eq!(moves.next().unwrap(), 2);
     assert_eq!(moves.next().unwrap(), 3);
@@ -251,9 +252,9 @@ fn test_c4_undo() {
 fn test_can_xo_play() {
     let mut game = Game::new_xo(3, 3, 3);
 
-    assert!(game.can_play(1 << 2));
+    assert!(game.can_play(2));
     game.place((0, 0));
-    assert!(!game.can_play(1 << 2));
+    assert!(!game.can_play(2));
 }
 
 #[test]
@@ -272,13 +273,13 @@ fn test_can_c4_play() {
 fn test_scoring_draw() {
     let mut game = Game::new_xo(3, 3, 3);
 
-    assert_eq!(game.evaluation(), 0);
+    assert_eq!(game.evaluation(), Evaluation::Draw);
 
     game.place((2, 0));
-    assert_eq!(game.evaluation(), 0);
+    assert_eq!(game.evaluation(), Evaluation::Draw);
 
     game.place((1, 1));
-    assert_eq!(game.evaluation(), 0);
+    assert_eq!(game.evaluation(), Evaluation::Draw);
 }
 
 #[test]
@@ -290,7 +291,7 @@ fn test_scoring_x_win() {
     game.place((1, 0));
     game.place((2, 2));
 
-    assert_eq!(game.evaluation(), 1)
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::X, plies: 1 })
 }
 
 #[test]
@@ -304,5 +305,1040 @@ fn test_scoring_o_win() {
     game.place((0, 2));
     // game.place((2, 1));
 
-    assert_eq!(game.evaluation(), -1)
-}
\ No newline at end of file
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::O, plies: 1 })
+}
+
+#[test]
+fn test_best_move_within_proven() {
+    let mut game = Game::new_xo(3, 3, 3);
+
+    game.place((0, 0)); // X
+    game.place((1, 1)); // O
+    game.place((0, 1)); // X
+
+    let result = game.best_move_within(Duration::from_secs(60));
+    assert!(result.proven);
+    assert_eq!(result.best_move, game.pos_to_move((0, 2)));
+}
+
+#[test]
+fn test_best_move_within_budget() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+
+    let now = Instant::now();
+    let result = game.best_move_within(Duration::from_millis(200));
+    assert!(now.elapsed() < Duration::from_secs(5));
+    assert!(!result.proven);
+    assert!(game.can_play(result.best_move));
+}
+
+#[test]
+fn test_depth_limited_blocks() {
+    let mut game = Game::new_xo(5, 5, 4);
+    game.solver.set_max_depth(Some(2));
+
+    game.place((0, 0)); // X
+    game.place((4, 4)); // O
+    game.place((0, 1)); // X
+    game.place((4, 3)); // O
+    game.place((0, 2)); // X
+
+    assert_eq!(game.best_move(), game.pos_to_move((0, 3)));
+}
+
+#[test]
+fn test_depth_limited_large_board() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    game.solver.set_max_depth(Some(6));
+
+    let best_move = game.best_move();
+    assert!(game.can_play(best_move));
+}
+
+#[test]
+fn test_evaluation_to_depth() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    game.placebit(3);
+    let now = Instant::now();
+    assert!(matches!(game.evaluation_to_depth(4), Evaluation::Heuristic(_)));
+    assert!(now.elapsed() < Duration::from_secs(5));
+
+    // Deep enough to reach the end of the game
+    let mut game = Game::new_xo(3, 3, 3);
+    assert_eq!(game.evaluation_to_depth(20), Evaluation::Draw);
+}
+
+#[test]
+fn test_shallow_search_keeps_solved_position() {
+    let mut game = Game::new_connect_four(5, 4, 4);
+    let solved = game.evaluation();
+
+    // The capped search's result for the same position mustn't replace the solved one
+    game.solver.set_max_depth(Some(2));
+    game.best_move();
+    game.solver.set_max_depth(None);
+    assert_eq!(game.evaluation(), solved);
+    // Straight from the table
+    assert!(game.search_stats().nodes <= 1);
+}
+
+#[test]
+fn test_cancel_search() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    let token = game.cancel_token();
+
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        token.cancel();
+    });
+
+    let now = Instant::now();
+    let best_move = game.best_move();
+    assert!(now.elapsed() < Duration::from_secs(5));
+    assert!(game.can_play(best_move));
+    assert!(game.cancel_token().is_cancelled());
+    canceller.join().unwrap();
+}
+
+#[test]
+fn test_cancel_weak_solve() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    let token = game.cancel_token();
+
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        token.cancel();
+    });
+
+    let now = Instant::now();
+    assert_eq!(game.weak_solve(), Outcome::Unknown);
+    assert!(now.elapsed() < Duration::from_secs(5));
+    canceller.join().unwrap();
+}
+
+#[test]
+fn test_cancel_mcts_search() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    game.set_engine(Engine::Mcts);
+    game.mcts.set_iterations(None);
+    game.mcts.set_time_limit(Some(Duration::from_secs(60)));
+    let token = game.cancel_token();
+
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        token.cancel();
+    });
+
+    let now = Instant::now();
+    let best_move = game.best_move();
+    assert!(now.elapsed() < Duration::from_secs(5));
+    assert!(game.can_play(best_move));
+    assert!(game.cancel_token().is_cancelled());
+    canceller.join().unwrap();
+}
+
+#[test]
+fn test_search_stats() {
+    let mut game = Game::new_xo(3, 3, 3);
+
+    game.best_move();
+    let stats = game.search_stats();
+    assert!(stats.nodes > 0);
+    assert!(stats.tt_hits <= stats.tt_probes);
+    assert!(stats.cutoffs > 0);
+    assert_eq!(stats.depth, 9);
+
+    let result = game.best_move_within(Duration::from_secs(60));
+    assert_eq!(result.stats.depth, 9);
+    assert!(result.stats.nodes > 0);
+}
+
+#[test]
+fn test_progress_callback() {
+    let mut game = Game::new_xo(4, 4, 3);
+    let calls = Arc::new(AtomicU32::new(0));
+    let counter = calls.clone();
+    game.solver.set_progress_callback(Some(Box::new(move |_, _| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    })));
+
+    let result = game.best_move_within(Duration::from_secs(60));
+    assert!(calls.load(Ordering::Relaxed) >= result.depth);
+}
+
+#[test]
+fn test_principal_variation() {
+    let mut game = Game::new_xo(3, 3, 3);
+
+    game.place((0, 0)); // X
+    game.place((1, 1)); // O
+    game.place((2, 2)); // X
+    game.place((0, 2)); // O
+
+    let best_move = game.best_move();
+    let line = game.principal_variation();
+    assert_eq!(line[0], best_move);
+    assert_eq!(line[0], game.pos_to_move((2, 0)));
+
+    // X forks, so O can only block one of the two threats
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::X, plies: line.len() as u32 });
+    for mov in line {
+        assert!(game.can_play(mov));
+        game.placebit(mov);
+    }
+    assert!(game.board.has_won(solver::Player::X));
+}
+
+#[test]
+fn test_move_to_pos() {
+    let game = Game::new_xo(4, 3, 3);
+
+    for row in 0..3 {
+        for col in 0..4 {
+            assert_eq!(game.move_to_pos(game.pos_to_move((row, col))), (row, col));
+        }
+    }
+}
+
+#[test]
+fn test_analyse_moves() {
+    let mut game = Game::new_xo(3, 3, 3);
+
+    game.place((0, 0)); // X
+    game.place((1, 1)); // O
+    game.place((0, 1)); // X
+    game.place((0, 2)); // O
+
+    let analysis = game.analyse_moves();
+    assert_eq!(analysis.len(), 5);
+    for (mov, eval) in analysis {
+        let expected = match game.move_to_pos(mov) {
+            (2, 0) => Evaluation::Draw, // Blocks O and draws
+            _ => Evaluation::Win { player: Player::O, plies: 2 }, // O wins on their next move
+        };
+        assert_eq!(eval, expected, "{:?}", game.move_to_pos(mov));
+    }
+}
+
+#[test]
+fn test_mirrored_position_reuses_table() {
+    let mut game = Game::new_xo(4, 4, 3);
+
+    game.place((0, 0));
+    game.place((1, 1));
+    let eval = game.evaluation();
+
+    game.board.undo_move(game.pos_to_move((1, 1)));
+    game.board.undo_move(game.pos_to_move((0, 0)));
+    game.place((3, 0)); // (0, 0) rotated by 270°
+    game.place((2, 1));
+    assert_eq!(game.evaluation(), eval);
+    assert_eq!(game.search_stats().nodes, 1);
+}
+
+#[test]
+fn test_mirrored_c4_best_move() {
+    let mut game = Game::new_connect_four(5, 4, 3);
+    for mov in [0, 1, 0] {
+        game.placebit(mov);
+    }
+    let best_move = game.best_move();
+
+    let mut mirrored = Game::new_connect_four(5, 4, 3);
+    for mov in [4, 3, 4] {
+        mirrored.placebit(mov);
+    }
+    mirrored.solver = game.solver;
+    assert_eq!(mirrored.principal_variation()[0], 4 - best_move);
+    assert_eq!(mirrored.best_move(), 4 - best_move);
+}
+
+#[test]
+fn test_tiny_transposition_table() {
+    let mut game = Game::new_xo(4, 4, 3);
+    game.solver.set_table_size(0); // Just one bucket
+
+    game.place((0, 1));
+    game.place((0, 2));
+    game.place((2, 3));
+    game.place((1, 2));
+
+    assert_eq!(game.best_move(), game.pos_to_move((2, 2)));
+}
+
+#[test]
+fn test_hash_transpositions() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    let empty = game.board.hash();
+
+    for mov in [3, 2, 4] {
+        game.placebit(mov);
+    }
+    let hash = game.board.hash();
+    assert_ne!(hash, empty);
+
+    let mut other = Game::new_connect_four(7, 6, 4);
+    for mov in [4, 2, 3] {
+        other.placebit(mov);
+    }
+    assert_eq!(other.board.hash(), hash);
+
+    for mov in [4, 2, 3] {
+        other.board.undo_move(mov);
+    }
+    assert_eq!(other.board.hash(), empty);
+}
+
+#[test]
+fn test_move_ordering_same_result() {
+    let mut ordered = Game::new_connect_four(5, 4, 4);
+    let mut unordered = Game::new_connect_four(5, 4, 4);
+    unordered.solver.set_move_ordering(MoveOrdering::none());
+
+    assert_eq!(ordered.evaluation(), unordered.evaluation());
+    assert!(ordered.search_stats().nodes < unordered.search_stats().nodes);
+}
+
+// Nodes a full solve takes on one thread, where the count is the same every time
+fn solve_nodes(mut game: Game, ordering: MoveOrdering) -> (Evaluation, u64) {
+    game.solver.set_move_ordering(ordering);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let evaluation = pool.install(|| game.evaluation());
+    (evaluation, game.search_stats().nodes)
+}
+
+#[test]
+fn test_move_ordering_toggles_help() {
+    let default = MoveOrdering::default();
+    let connect_four = || {
+        let mut game = Game::new_connect_four(6, 5, 4);
+        for mov in [2, 3, 1, 4, 2, 3] {
+            game.placebit(mov);
+        }
+        game
+    };
+    let xo = || Game::new_xo(4, 4, 4);
+
+    // Turning off any of the defaults gives the same result but takes more nodes
+    for (game, toggle) in [
+        (xo as fn() -> Game, MoveOrdering { centre_first: false, ..default }),
+        (xo, MoveOrdering { tt_move: false, ..default }),
+        (connect_four, MoveOrdering { history: false, ..default }),
+    ] {
+        let (evaluation, nodes) = solve_nodes(game(), default);
+        let (toggled_evaluation, toggled_nodes) = solve_nodes(game(), toggle);
+        assert_eq!(evaluation, toggled_evaluation);
+        assert!(nodes < toggled_nodes, "{:?}: {} nodes, {} with {:?}", evaluation, nodes, toggled_nodes, toggle);
+    }
+}
+
+#[test]
+fn test_c4_immediate_win() {
+    let mut game = Game::new_connect_four(5, 4, 4);
+    for mov in [0, 4, 1, 4, 2, 4] {
+        game.placebit(mov);
+    }
+
+    assert_eq!(game.best_move(), 3);
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::X, plies: 1 });
+}
+
+#[test]
+fn test_c4_forced_block() {
+    let mut game = Game::new_connect_four(5, 4, 4);
+    for mov in [4, 0, 4, 1, 0, 2] {
+        game.placebit(mov);
+    }
+
+    // O threatens to finish the bottom row in column 3
+    assert_eq!(game.best_move(), 3);
+}
+
+#[test]
+fn test_c4_avoid_playing_under_threat() {
+    let mut game = Game::new_connect_four(6, 5, 4);
+    for mov in [1, 2, 3, 1, 5, 2, 5, 3] {
+        game.placebit(mov);
+    }
+    // O wins on top of columns 0 and 4, so X loses straight away by playing there
+
+    for (mov, eval) in game.analyse_moves() {
+        match (mov, eval) {
+            (0 | 4, _) => assert_eq!(eval, Evaluation::Win { player: Player::O, plies: 2 }),
+            (_, Evaluation::Win { player: Player::O, plies }) => assert!(plies > 2),
+            _ => panic!("{} should lose, not {}", mov, eval),
+        }
+    }
+}
+
+#[test]
+fn test_search_modes_agree() {
+    for mode in [SearchMode::Pvs, SearchMode::Mtdf] {
+        let mut game = Game::new_connect_four(5, 4, 4);
+        let mut reference = Game::new_connect_four(5, 4, 4);
+        game.solver.set_search_mode(mode);
+
+        assert_eq!(game.evaluation(), reference.evaluation());
+        for mov in [2, 2, 1] {
+            game.placebit(mov);
+            reference.placebit(mov);
+            assert_eq!(game.evaluation(), reference.evaluation(), "{:?}", mode);
+            assert_eq!(game.analyse_moves(), reference.analyse_moves(), "{:?}", mode);
+        }
+    }
+}
+
+#[test]
+fn test_mtdf_xo() {
+    let mut game = Game::new_xo(3, 3, 3);
+    game.solver.set_search_mode(SearchMode::Mtdf);
+
+    game.place((0, 0));
+    game.place((1, 1));
+    game.place((1, 0));
+    game.place((2, 2));
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::X, plies: 1 });
+    assert_eq!(game.best_move(), game.pos_to_move((2, 0)));
+}
+
+#[test]
+fn test_weak_solve_xo() {
+    let mut game = Game::new_xo(3, 3, 3);
+    assert_eq!(game.weak_solve(), Outcome::Draw);
+
+    game.place((0, 0));
+    game.place((1, 1));
+    game.place((1, 0));
+    game.place((2, 2));
+    assert_eq!(game.weak_solve(), Outcome::Win);
+
+    game.place((0, 1));
+    assert_eq!(game.weak_solve(), Outcome::Loss);
+}
+
+#[test]
+fn test_weak_solve_matches_strong() {
+    let mut game = Game::new_connect_four(5, 4, 4);
+    for mov in [2, 2, 1, 3, 3] {
+        let mut strong = Game::new_connect_four(5, 4, 4);
+        strong.board = game.board.clone();
+        let expected = match strong.evaluation() {
+            Evaluation::Win { player, .. } if player == game.board.player() => Outcome::Win,
+            Evaluation::Win { .. } => Outcome::Loss,
+            _ => Outcome::Draw,
+        };
+        assert_eq!(game.weak_solve(), expected);
+        game.placebit(mov);
+    }
+}
+
+#[test]
+fn test_evaluation_display() {
+    assert_eq!(Evaluation::Win { player: Player::X, plies: 1 }.to_string(), "X wins in 1 turn");
+    assert_eq!(Evaluation::Win { player: Player::O, plies: 4 }.to_string(), "O wins in 2 turns");
+    assert_eq!(Evaluation::Win { player: Player::O, plies: 0 }.to_string(), "O has won");
+    assert_eq!(Evaluation::Draw.to_string(), "Draw");
+    assert_eq!(Evaluation::Heuristic(-12).to_string(), "Unclear (-12 for X)");
+}
+
+#[test]
+fn test_search_result_evaluation() {
+    let mut game = Game::new_xo(4, 4, 4);
+    game.solver.set_max_depth(Some(2));
+    let result = game.best_move_within(Duration::from_secs(10));
+    assert!(matches!(result.evaluation, Evaluation::Heuristic(_)));
+
+    let mut game = Game::new_xo(3, 3, 3);
+    game.place((0, 0));
+    game.place((1, 1));
+    game.place((1, 0));
+    game.place((2, 2));
+    let result = game.best_move_within(Duration::from_secs(10));
+    assert_eq!(result.evaluation, Evaluation::Win { player: Player::X, plies: 1 });
+}
+
+#[test]
+fn test_opening_book() {
+    let mut generator = Game::new_connect_four(5, 4, 4);
+    let book = OpeningBook::generate(&mut generator, 2, |_, _| {});
+    // Mirrored positions are only stored once
+    assert_eq!(book.len(), 1 + 3 + 13);
+
+    let mut bytes = Vec::new();
+    book.write_to(&mut bytes).unwrap();
+    let book = OpeningBook::read_from(bytes.as_slice()).unwrap();
+
+    let mut game = Game::new_connect_four(5, 4, 4);
+    let mut reference = Game::new_connect_four(5, 4, 4);
+    assert!(book.matches(&game.board));
+    game.solver.set_opening_book(Some(book));
+
+    // The first move only needs to look up its replies
+    assert_eq!(game.best_move(), reference.best_move());
+    assert!(game.search_stats().nodes < 10);
+    assert_eq!(game.evaluation(), reference.evaluation());
+    assert_eq!(game.analyse_moves(), reference.analyse_moves());
+
+    for mov in [2, 1, 1] {
+        game.placebit(mov);
+        reference.placebit(mov);
+        assert_eq!(game.evaluation(), reference.evaluation());
+    }
+}
+
+#[test]
+fn test_opening_book_other_game() {
+    let mut generator = Game::new_connect_four(4, 4, 3);
+    let book = OpeningBook::generate(&mut generator, 1, |_, _| {});
+    let mut game = Game::new_connect_four(5, 4, 4);
+    assert!(!book.matches(&game.board));
+
+    let eval = game.evaluation();
+    game.solver.set_opening_book(Some(book));
+    game.solver.clear_transpositions();
+    assert_eq!(game.evaluation(), eval);
+}
+
+#[test]
+fn test_opening_book_invalid() {
+    assert!(OpeningBook::read_from(&b"not a book at all"[..]).is_err());
+
+    let mut generator = Game::new_xo(3, 3, 3);
+    let book = OpeningBook::generate(&mut generator, 1, |_, _| {});
+    let mut bytes = Vec::new();
+    book.write_to(&mut bytes).unwrap();
+    bytes.truncate(bytes.len() - 1);
+    assert!(OpeningBook::read_from(bytes.as_slice()).is_err());
+}
+
+#[test]
+fn test_save_transpositions() {
+    let mut game = Game::new_connect_four(5, 4, 4);
+    let eval = game.evaluation();
+    let nodes = game.search_stats().nodes;
+    let mut bytes = Vec::new();
+    game.save_transpositions(&mut bytes).unwrap();
+
+    let mut loaded = Game::new_connect_four(5, 4, 4);
+    loaded.load_transpositions(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.evaluation(), eval);
+    assert!(loaded.search_stats().nodes < nodes / 10);
+}
+
+#[test]
+fn test_load_transpositions_other_game() {
+    let mut game = Game::new_connect_four(4, 4, 3);
+    game.evaluation();
+    let mut bytes = Vec::new();
+    game.save_transpositions(&mut bytes).unwrap();
+
+    assert!(Game::new_connect_four(5, 4, 3).load_transpositions(bytes.as_slice()).is_err());
+    assert!(Game::new_xo(4, 4, 3).load_transpositions(bytes.as_slice()).is_err());
+    assert!(Game::new_connect_four(4, 4, 3).load_transpositions(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Game::new_connect_four(4, 4, 3).load_transpositions(&b"garbage"[..]).is_err());
+}
+
+#[test]
+fn test_tablebase_xo() {
+    let mut game = Game::new_xo(3, 3, 3);
+    let tablebase = Tablebase::generate(&game);
+    assert_eq!(tablebase.evaluation(&game.board), Some(Evaluation::Draw));
+
+    // Check every position along a few games against the search
+    for moves in [[(0, 0), (1, 1), (1, 0), (2, 2), (2, 0)], [(1, 1), (0, 0), (2, 2), (1, 0), (0, 2)]] {
+        let mut game = Game::new_xo(3, 3, 3);
+        for pos in moves {
+            game.place(pos);
+            let expected = game.evaluation();
+            assert_eq!(tablebase.evaluation(&game.board), Some(expected));
+        }
+    }
+
+    game.solver.set_tablebase(Some(tablebase));
+    game.best_move();
+    assert!(game.search_stats().nodes < 20);
+}
+
+#[test]
+fn test_tablebase_c4() {
+    let mut game = Game::new_connect_four(4, 4, 3);
+    let tablebase = Tablebase::generate(&game);
+    assert!(tablebase.matches(&game.board));
+    assert!(!tablebase.matches(&Game::new_xo(4, 4, 3).board));
+
+    let mut reference = Game::new_connect_four(4, 4, 3);
+    game.solver.set_tablebase(Some(tablebase));
+    assert_eq!(game.analyse_moves(), reference.analyse_moves());
+    for mov in [1, 2, 2, 0] {
+        game.placebit(mov);
+        reference.placebit(mov);
+        assert_eq!(game.evaluation(), reference.evaluation());
+        assert_eq!(game.best_move(), reference.best_move());
+    }
+}
+
+#[test]
+fn test_mcts_takes_win() {
+    let mut game = Game::new_connect_four(7, 6, 4);
+    game.set_engine(Engine::Mcts);
+    game.mcts.set_iterations(Some(500));
+    for mov in [0, 6, 1, 6, 2, 5] {
+        game.placebit(mov);
+    }
+
+    assert_eq!(game.best_move(), 3);
+    assert!(game.mcts.win_rate().unwrap() > 0.9);
+    assert_eq!(game.principal_variation()[0], 3);
+}
+
+#[test]
+fn test_mcts_blocks_large_board() {
+    let mut game = Game::new_xo(7, 7, 5);
+    game.set_engine(Engine::Mcts);
+    game.mcts.set_iterations(Some(2000));
+    for pos in [(3, 0), (0, 0), (3, 1), (0, 6), (3, 2), (6, 6), (3, 3)] {
+        game.place(pos);
+    }
+
+    // X has four in a row against the edge, so O has to block the other end
+    let best_move = game.best_move();
+    assert!(best_move == game.pos_to_move((3, 4)), "{:?}", game.move_to_pos(best_move));
+    assert!(game.search_stats().nodes >= 2000);
+}
+
+#[test]
+fn test_mcts_time_limit() {
+    let mut game = Game::new_xo(7, 7, 5);
+    game.set_engine(Engine::Mcts);
+    game.mcts.set_iterations(None);
+    let now = Instant::now();
+    let result = game.best_move_within(Duration::from_millis(200));
+    assert!(now.elapsed() < Duration::from_secs(2));
+    assert!(game.can_play(result.best_move));
+    assert!(!result.proven);
+}
+
+#[test]
+fn test_mcts_seeded() {
+    let mut moves = Vec::new();
+    for _ in 0..2 {
+        let mut game = Game::new_connect_four(7, 6, 4);
+        game.set_engine(Engine::Mcts);
+        game.mcts.set_iterations(Some(200));
+        game.mcts.set_playouts(4);
+        game.mcts.set_seed(7);
+        moves.push((game.best_move(), game.mcts.win_rate()));
+    }
+    assert_eq!(moves[0], moves[1]);
+}
+
+#[test]
+fn test_prove_win_xo() {
+    let game = Game::new_xo(4, 4, 3);
+    match game.prove_win(Player::X, 1_000_000) {
+        Proof::Win(tree) => assert!(tree.verify(&game.board, Player::X)),
+        proof => panic!("X should win 4 4 3, not {:?}", proof),
+    }
+    assert_eq!(game.prove_win(Player::O, 1_000_000), Proof::NoWin);
+
+    let game = Game::new_xo(3, 3, 3);
+    assert_eq!(game.prove_win(Player::X, 1_000_000), Proof::NoWin);
+    assert_eq!(game.prove_win(Player::X, 10), Proof::Unknown);
+}
+
+#[test]
+fn test_prove_win_matches_weak_solve() {
+    let mut game = Game::new_connect_four(4, 4, 3);
+    for mov in [1, 2, 2, 0, 3] {
+        game.placebit(mov);
+        let player = game.board.player();
+        let proof = game.prove_win(player, 10_000_000);
+        match game.weak_solve() {
+            Outcome::Win => match proof {
+                Proof::Win(tree) => assert!(tree.verify(&game.board, player)),
+                proof => panic!("{:?}", proof),
+            },
+            _ => assert_eq!(proof, Proof::NoWin),
+        }
+    }
+}
+
+#[test]
+fn test_proof_tree_forced_win() {
+    let mut game = Game::new_xo(3, 3, 3);
+    game.place((0, 0)); // X
+    game.place((1, 1)); // O
+    game.place((2, 2)); // X
+    game.place((0, 2)); // O
+
+    // X has to block at (2, 0), which makes two threats
+    match game.prove_win(Player::X, 1000) {
+        Proof::Win(tree) => {
+            assert!(tree.verify(&game.board, Player::X));
+            assert!(matches!(tree, solver::ProofTree::Attack(mov, _) if mov == game.pos_to_move((2, 0))));
+            // The move, O's four replies, then X's winning move after each of them
+            assert!(tree.size() <= 1 + 1 + 4 * 2);
+        },
+        proof => panic!("{:?}", proof),
+    }
+}
+
+// Noughts and crosses where the middle can't be played until all four corners have
+// been, although it still counts as empty, so winning_cells can point at a cell no
+// move plays in yet
+#[derive(Clone)]
+struct MiddleLast;
+
+impl GameRules for MiddleLast {
+    fn id(&self) -> u8 { 101 }
+
+    fn generate_moves<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>) -> Moves<B> {
+        let (height, width) = (board.height() - 1, board.width() - 1);
+        let corners = [(0, 0), (0, width), (height, 0), (height, width)].into_iter()
+            .fold(B::ZERO, |corners, (row, column)| corners | B::bit(board.get_index(row, column)));
+        let playable = self.playable_cells(board);
+        let middle = match (playable & corners).is_empty() {
+            true => B::ZERO,
+            false => B::bit(board.get_index(height / 2, width / 2)),
+        };
+        Moves::XOMoves(playable & !middle, 0, board.used_bits())
+    }
+}
+
+#[test]
+fn test_proof_unplayable_winning_cell() {
+    let mut game = Game::with_rules(MiddleLast, 3, 3, 3);
+    game.place((0, 0)); // X
+    game.place((0, 1)); // O
+    game.place((2, 2)); // X
+    game.place((0, 2)); // O
+
+    // X's line through the middle can't be finished yet, so it has to be proven the long
+    // way, by forking
+    match game.prove_win(Player::X, 100_000) {
+        Proof::Win(tree) => {
+            assert!(tree.verify(&game.board, Player::X));
+            assert!(!matches!(tree, solver::ProofTree::Attack(mov, _) if mov == game.pos_to_move((1, 1))));
+        },
+        proof => panic!("{:?}", proof),
+    }
+}
+
+#[test]
+fn test_search_unplayable_winning_cell() {
+    let mut game = Game::with_rules(MiddleLast, 3, 3, 3);
+    game.place((0, 0)); // X
+    game.place((0, 1)); // O
+    game.place((2, 2)); // X
+    game.place((0, 2)); // O
+
+    // Not a win in 1 through the middle, but forking wins in 3
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::X, plies: 3 });
+
+    game.set_engine(Engine::Mcts);
+    game.mcts.set_iterations(Some(200));
+    let best_move = game.best_move();
+    assert!(game.can_play(best_move));
+}
+
+#[test]
+fn test_difficulty_seeded() {
+    let mut games = Vec::new();
+    for _ in 0..2 {
+        let mut game = Game::new_connect_four(5, 4, 4);
+        game.solver.set_difficulty(Difficulty::easy());
+        game.solver.set_seed(42);
+        let mut moves = Vec::new();
+        for _ in 0..6 {
+            let mov = game.best_move();
+            game.placebit(mov);
+            moves.push(mov);
+        }
+        games.push(moves);
+    }
+    assert_eq!(games[0], games[1]);
+}
+
+#[test]
+fn test_difficulty_depth() {
+    let mut game = Game::new_xo(4, 4, 4);
+    game.solver.set_difficulty(Difficulty::easy());
+    game.best_move();
+    assert_eq!(game.search_stats().depth, 2);
+}
+
+#[test]
+fn test_difficulty_random_moves() {
+    let mut game = Game::new_xo(3, 3, 3);
+    game.solver.set_difficulty(Difficulty { random_move_chance: 1.0, ..Difficulty::perfect() });
+    let moves: HashSet<Move> = (0..20).map(|seed| {
+        game.solver.set_seed(seed);
+        game.best_move()
+    }).collect();
+    assert!(moves.len() > 1);
+}
+
+#[test]
+fn test_difficulty_suboptimal() {
+    let mut game = Game::new_xo(3, 3, 3);
+    game.place((0, 0)); // X
+    game.place((1, 1)); // O
+    game.place((0, 1)); // X
+    game.place((0, 2)); // O
+    game.solver.set_difficulty(Difficulty { suboptimal_move_chance: 1.0, ..Difficulty::perfect() });
+
+    // Not blocking loses
+    assert_ne!(game.best_move(), game.pos_to_move((2, 0)));
+}
+
+#[test]
+fn test_difficulty_relaxed_never_loses() {
+    for seed in 0..4 {
+        let mut game = Game::new_xo(3, 3, 3);
+        let mut opponent = Game::new_xo(3, 3, 3);
+        game.solver.set_difficulty(Difficulty { relaxed: true, ..Difficulty::perfect() });
+        game.solver.set_seed(seed);
+        while !game.board.over() {
+            let mov = match game.board.player() {
+                Player::X => game.best_move(),
+                Player::O => {
+                    opponent.board = game.board.clone();
+                    opponent.best_move()
+                },
+            };
+            game.placebit(mov);
+        }
+        assert!(!game.board.has_won(Player::O));
+    }
+}
+
+#[test]
+fn test_difficulty_relaxed_keeps_wins() {
+    // X wins with every move but (2, 1), which only draws
+    for seed in 0..50 {
+        let mut game = Game::new_xo(3, 3, 3);
+        game.place((1, 1));
+        game.place((0, 1));
+        game.solver.set_difficulty(Difficulty { relaxed: true, ..Difficulty::perfect() });
+        game.solver.set_seed(seed);
+        let mov = game.best_move();
+        game.placebit(mov);
+        assert!(matches!(game.evaluation(), Evaluation::Win { player: Player::X, .. }), "{:?}", game.move_to_pos(mov));
+    }
+}
+
+// Noughts and crosses where getting three in a row loses, to check rules from outside the crate work
+#[derive(Clone)]
+struct Misere;
+
+impl GameRules for Misere {
+    fn id(&self) -> u8 { 100 }
+
+    fn has_won<R: GameRules, B: Bitboard>(&self, board: &Board<R, B>, player: Player) -> bool {
+        board.has_line(!player)
+    }
+
+    // Completing a line doesn't win, so there are no quick wins to look for
+    fn winning_cells<R: GameRules, B: Bitboard>(&self, _board: &Board<R, B>, _player: Player) -> B {
+        B::ZERO
+    }
+}
+
+#[test]
+fn test_custom_rules() {
+    let mut game = Game::with_rules(Misere, 3, 3, 3);
+    assert_eq!(game.evaluation(), Evaluation::Draw);
+
+    // O has just made a line, so X wins
+    for pos in [(0, 0), (1, 0), (0, 1), (1, 1), (2, 2), (1, 2)] {
+        game.place(pos);
+    }
+    assert!(game.board.has_won(Player::X));
+    assert_eq!(game.evaluation(), Evaluation::Win { player: Player::X, plies: 0 });
+
+    let mut game = Game::with_rules(Misere, 3, 3, 3);
+    let mov = game.board.parse_move("1-1").unwrap();
+    assert_eq!(game.board.format_move(mov), "1-1");
+    assert_eq!(mov, game.pos_to_move((1, 1)));
+    game.placebit(mov);
+    assert_eq!(game.analyse_moves().len(), 8);
+}
+
+#[test]
+fn test_rules_move_text() {
+    let game = Game::new_connect_four(7, 6, 4);
+    assert_eq!(game.board.parse_move("3"), Some(3));
+    assert_eq!(game.board.parse_move("x"), None);
+    let game = Game::new_xo(3, 3, 3);
+    assert_eq!(game.board.parse_move("3-0"), None);
+    assert_eq!(game.board.format_move(game.pos_to_move((2, 1))), "2-1");
+}
+
+#[test]
+fn test_long_rows() {
+    // Four out of five in a row with a gap in the middle isn't a win
+    let mut game = Game::new_xo(6, 6, 5);
+    for column in [0, 1, 3, 4] {
+        game.board.bitboards[0] |= 1 << game.board.get_index(2, column);
+    }
+    assert!(!game.board.has_won(Player::X));
+    game.board.bitboards[0] |= 1 << game.board.get_index(2, 2);
+    assert!(game.board.has_won(Player::X));
+
+    // Same down a column and diagonally
+    let mut game = Game::new_xo(6, 6, 5);
+    for row in [0, 1, 3, 4] {
+        game.board.bitboards[0] |= 1 << game.board.get_index(row, 5);
+        game.board.bitboards[1] |= 1 << game.board.get_index(row, row);
+    }
+    assert!(!game.board.has_won(Player::X) && !game.board.has_won(Player::O));
+
+    // Any piece wins when one in a row is enough
+    let mut game = Game::new_xo(3, 3, 1);
+    assert!(!game.board.has_won(Player::X));
+    game.place((1, 1));
+    assert!(game.board.has_won(Player::X));
+}
+
+#[test]
+fn test_wide_bitboards() {
+    let bit = Bitboard256::bit(63);
+    assert_eq!(bit << 1, Bitboard256::bit(64));
+    assert_eq!((bit << 130) >> 129, Bitboard256::bit(64));
+    assert_eq!(Bitboard256::low_bits(64).wrapping_add(Bitboard256::bit(0)), Bitboard256::bit(64));
+    assert_eq!(Bitboard256::low_bits(200).count_ones(), 200);
+    assert_eq!(Bitboard256::bit(150).trailing_zeros(), 150);
+    assert_eq!(Bitboard256::bit(150).leading_zeros(), 105);
+    assert_eq!(Bitboard256::bit(255).checked_shl(1), Some(Bitboard256::ZERO));
+    assert_eq!(Bitboard256::bit(0).checked_shr(256), None);
+}
+
+#[test]
+#[should_panic]
+fn test_board_too_big() {
+    Game::new_xo(8, 8, 5);
+}
+
+#[test]
+fn test_big_boards() {
+    // Gomoku needs more than a u128
+    let mut game = Game::<BoardKind, Bitboard256>::new(BoardKind::XOBoard, 15, 15, 5);
+    assert_eq!(game.board.generate_moves().count(), 225);
+    for (x, o) in [((14, 10), (0, 0)), ((14, 11), (0, 1)), ((14, 12), (0, 2)), ((14, 13), (1, 0))] {
+        game.place(x);
+        game.place(o);
+    }
+    assert!(!game.board.over());
+    game.solver.set_max_depth(Some(2));
+    let best_move = game.best_move();
+    assert!([(14, 9), (14, 14)].contains(&game.move_to_pos(best_move)));
+    game.placebit(best_move);
+    assert!(game.board.has_won(Player::X));
+
+    // Connect Four on a u128 gets the same results as on a u64
+    let mut small = Game::new_connect_four(5, 4, 4);
+    let mut wide = Game::<BoardKind, u128>::new(BoardKind::C4Board, 5, 4, 4);
+    for mov in [2, 2, 1] {
+        small.placebit(mov);
+        wide.placebit(mov);
+    }
+    assert_eq!(small.evaluation(), wide.evaluation());
+}
+
+#[test]
+fn test_game_config() {
+    let game: Game = GameConfig::connect_four().build().unwrap();
+    assert_eq!((game.board.width(), game.board.height(), game.board.row()), (7, 6, 4));
+    let game: Game = GameConfig::xo().width(4).row(4).build().unwrap();
+    assert_eq!((game.board.width(), game.board.height(), game.board.row()), (4, 3, 4));
+
+    assert_eq!(GameConfig::xo().width(0).build::<u64>().err(), Some(ConfigError::EmptyBoard { width: 0, height: 3 }));
+    assert_eq!(GameConfig::xo().row(0).build::<u64>().err(), Some(ConfigError::ZeroRow));
+    assert_eq!(GameConfig::xo().row(4).build::<u64>().err(), Some(ConfigError::RowTooLong { row: 4, width: 3, height: 3 }));
+    assert_eq!(GameConfig::connect_four().width(17).height(2).build::<u64>().err(), Some(ConfigError::TooWide { width: 17, max_width: 16 }));
+
+    // Too big for a u64, but fine in something wider
+    let config = GameConfig::xo().width(8).height(8).row(5);
+    assert_eq!(config.build::<u64>().err(), Some(ConfigError::TooBig { width: 8, height: 8, bits: 72, capacity: 64 }));
+    assert!(config.build::<u128>().is_ok());
+    assert!(GameConfig::xo().width(15).height(15).row(5).build::<Bitboard256>().is_ok());
+    assert!(GameConfig::xo().width(16).height(16).row(5).build::<Bitboard256>().is_err());
+    assert!(GameConfig::xo().width(u32::MAX).height(u32::MAX).build::<Bitboard256>().is_err());
+
+    // Rules from outside the crate
+    assert!(GameConfig::new(Misere).build::<u64>().is_ok());
+}
+
+#[test]
+fn test_try_place() {
+    let mut game = Game::new_xo(3, 3, 3);
+    assert_eq!(game.try_place((3, 0)), Err(MoveError::OutOfBounds));
+    assert_eq!(game.try_place((0, 3)), Err(MoveError::OutOfBounds));
+    assert_eq!(game.try_place((1, 1)), Ok(()));
+    assert_eq!(game.try_place((1, 1)), Err(MoveError::Occupied));
+    // The spare bit at the top of a column isn't a cell
+    assert_eq!(game.try_play(3), Err(MoveError::OutOfBounds));
+    assert_eq!(game.try_play(100), Err(MoveError::OutOfBounds));
+    assert_eq!(game.board.moves_played(), 1);
+
+    for pos in [(0, 0), (1, 0), (0, 1), (1, 2)] {
+        game.try_place(pos).unwrap();
+    }
+    assert!(game.board.has_won(Player::X));
+    assert_eq!(game.try_place((2, 2)), Err(MoveError::GameOver));
+}
+
+#[test]
+fn test_try_play() {
+    let mut game = Game::new_connect_four(4, 3, 3);
+    assert_eq!(game.try_play(4), Err(MoveError::OutOfBounds));
+    assert_eq!(game.try_place((2, 0)), Err(MoveError::WrongMoveKind));
+    for _ in 0..3 {
+        game.try_play(0).unwrap();
+    }
+    assert_eq!(game.try_play(0), Err(MoveError::ColumnFull));
+    assert_eq!(game.board.moves_played(), 3);
+
+    for mov in [1, 2, 1, 2, 1] {
+        game.try_play(mov).unwrap();
+    }
+    assert!(game.board.has_won(Player::O));
+    assert_eq!(game.try_play(3), Err(MoveError::GameOver));
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut game = Game::new_connect_four(4, 4, 3);
+    assert_eq!(game.undo(), None);
+    game.placebit(1);
+    game.try_play(2).unwrap();
+    game.placebit(1);
+    assert_eq!(game.try_play(9), Err(MoveError::OutOfBounds));
+    assert_eq!(game.history(), &[1, 2, 1]);
+    assert_eq!(game.ply(), 3);
+    let board = game.board.clone();
+
+    assert_eq!(game.undo(), Some(1));
+    assert_eq!(game.undo(), Some(2));
+    assert_eq!(game.ply(), 1);
+    assert_eq!(game.board.player(), Player::O);
+    assert_eq!(game.board.moves_played(), 1);
+
+    assert_eq!(game.redo(), Some(2));
+    assert_eq!(game.redo(), Some(1));
+    assert_eq!(game.redo(), None);
+    assert_eq!(game.board.bitboards, board.bitboards);
+    assert_eq!(game.board.hash(), board.hash());
+
+    // Playing something new forgets what was undone
+    game.undo();
+    game.placebit(3);
+    assert_eq!(game.redo(), None);
+    assert_eq!(game.history(), &[1, 2, 3]);
+
+    for _ in 0..3 {
+        game.undo();
+    }
+    assert_eq!(game.board.bitboards, [0, 0]);
+    assert_eq!(game.board.hash(), 0);
+}